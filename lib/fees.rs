@@ -0,0 +1,75 @@
+// Trading fees.
+//
+// A fee is a fraction of a trade's collateral, charged on top of the cost of
+// a buy and taken out of the refund of a sell. The engine keeps it apart
+// from the collateral backing the shares, in `fees_collected`, until an
+// operator withdraws it. Rates are in basis points and fees round down, like
+// the rest of the fixed-point math.
+use serde::{Deserialize, Serialize};
+
+use crate::fixed;
+use crate::types::DECIMALS;
+
+/// Basis points in 100%.
+pub const BPS: u32 = 10_000;
+
+/// How a market charges fees. Serialized externally tagged, like journal
+/// entries, as serde cannot buffer the `u128`s of an internally tagged enum.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeSchedule {
+    #[default]
+    None,
+    /// `bps` of the trade's collateral.
+    Flat { bps: u32 },
+    /// `bps` of the collateral scaled by `4 p (1 - p)`, where `p` is the
+    /// trade's average price: the full rate at even odds, falling to nothing
+    /// as the outcome becomes certain.
+    PriceDependent { bps: u32 },
+    /// The rate of the highest tier the trader's past volume reaches, or
+    /// nothing below the lowest.
+    Tiered { tiers: Vec<FeeTier> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeTier {
+    /// Collateral the trader must already have traded in the market.
+    pub min_volume: u128,
+    pub bps: u32,
+}
+
+impl FeeSchedule {
+    /// True if no rate is above 100%.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            FeeSchedule::None => true,
+            FeeSchedule::Flat { bps } | FeeSchedule::PriceDependent { bps } => *bps <= BPS,
+            FeeSchedule::Tiered { tiers } => tiers.iter().all(|tier| tier.bps <= BPS),
+        }
+    }
+
+    /// The fee on trading `shares` for `collateral`, by a trader who has
+    /// already traded `volume`. `None` on overflow.
+    pub fn fee(&self, collateral: u128, shares: u128, volume: u128) -> Option<u128> {
+        let bps = match self {
+            FeeSchedule::None => return Some(0),
+            FeeSchedule::Flat { bps } => *bps,
+            FeeSchedule::PriceDependent { bps } => {
+                if shares == 0 {
+                    return Some(0);
+                }
+                // Prices above 1 (as under LS-LMSR) count as certain
+                let p = fixed::checked_div(collateral, shares)?.min(DECIMALS);
+                let scale = 4 * fixed::mul(p, DECIMALS - p);
+                let fee = fixed::checked_mul(collateral, scale)?;
+                return fixed::checked_mul_div(fee, u128::from(*bps), u128::from(BPS));
+            }
+            FeeSchedule::Tiered { tiers } => tiers
+                .iter()
+                .filter(|tier| tier.min_volume <= volume)
+                .max_by_key(|tier| tier.min_volume)
+                .map_or(0, |tier| tier.bps),
+        };
+        fixed::checked_mul_div(collateral, u128::from(bps), u128::from(BPS))
+    }
+}
@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use super::super::fees::*;
+    use super::super::types::DECIMALS;
+
+    #[test]
+    fn test_flat_fee() {
+        let fees = FeeSchedule::Flat { bps: 30 };
+        assert_eq!(fees.fee(100 * DECIMALS, 200 * DECIMALS, 0), Some(3 * DECIMALS / 10));
+        assert_eq!(fees.fee(0, 0, 0), Some(0));
+        assert_eq!(FeeSchedule::None.fee(100 * DECIMALS, 200 * DECIMALS, 0), Some(0));
+    }
+
+    #[test]
+    fn test_price_dependent_fee() {
+        let fees = FeeSchedule::PriceDependent { bps: 100 };
+
+        // Full rate at even odds
+        assert_eq!(fees.fee(50 * DECIMALS, 100 * DECIMALS, 0), Some(DECIMALS / 2));
+        // 4 * 0.9 * 0.1 = 0.36 of the rate near certainty
+        assert_eq!(fees.fee(90 * DECIMALS, 100 * DECIMALS, 0), Some(DECIMALS * 324 / 1000));
+        // Nothing once certain, or beyond
+        assert_eq!(fees.fee(100 * DECIMALS, 100 * DECIMALS, 0), Some(0));
+        assert_eq!(fees.fee(110 * DECIMALS, 100 * DECIMALS, 0), Some(0));
+        assert_eq!(fees.fee(0, 0, 0), Some(0));
+    }
+
+    #[test]
+    fn test_tiered_fee() {
+        let fees = FeeSchedule::Tiered {
+            tiers: vec![
+                FeeTier { min_volume: 1_000 * DECIMALS, bps: 10 },
+                FeeTier { min_volume: 0, bps: 50 },
+                FeeTier { min_volume: 100 * DECIMALS, bps: 25 },
+            ],
+        };
+        let fee = |volume| fees.fee(100 * DECIMALS, 100 * DECIMALS, volume).unwrap();
+        assert_eq!(fee(0), DECIMALS / 2);
+        assert_eq!(fee(100 * DECIMALS - 1), DECIMALS / 2);
+        assert_eq!(fee(100 * DECIMALS), DECIMALS / 4);
+        assert_eq!(fee(5_000 * DECIMALS), DECIMALS / 10);
+
+        let above_floor = FeeSchedule::Tiered {
+            tiers: vec![FeeTier { min_volume: DECIMALS, bps: 10 }],
+        };
+        assert_eq!(above_floor.fee(100 * DECIMALS, 100 * DECIMALS, 0), Some(0));
+    }
+
+    #[test]
+    fn test_fee_validation() {
+        assert!(FeeSchedule::Flat { bps: BPS }.is_valid());
+        assert!(!FeeSchedule::PriceDependent { bps: BPS + 1 }.is_valid());
+        assert!(!FeeSchedule::Tiered {
+            tiers: vec![FeeTier { min_volume: 0, bps: 20_000 }]
+        }
+        .is_valid());
+    }
+}
@@ -0,0 +1,232 @@
+// Deterministic 1e18 fixed-point math.
+//
+// Every value is a u128 scaled by DECIMALS (1e18 == 1.0). Only integer
+// operations are used, so results are bit-identical on every platform.
+//
+// Error bounds (in units of the last place, 1e-18):
+// - mul / div / mul_div: exact floor of the true result
+// - sqrt: exact floor for x <= ~3.4e20 (u128::MAX / 1e18), otherwise
+//   relative error below 1e-18
+// - exp: within 1 ulp of the true result; relative error below 1e-17
+//   once the result is above 0.1
+// - ln: within 1 ulp of the true result
+use crate::types::DECIMALS;
+
+/// ln(2) at 1e18 scale, rounded down.
+pub const LN_2: u128 = 693_147_180_559_945_309;
+
+// ln(2) scaled by 1e36, used for range reduction in exp and ln.
+const LN_2_E36: u128 = 693_147_180_559_945_309_417_232_121_458_176_568;
+
+const E36: u128 = DECIMALS * DECIMALS;
+
+// Below this, exp(x) rounds to zero at 18 decimals.
+const EXP_MIN: i128 = -42_000_000_000_000_000_000;
+
+const MASK_64: u128 = u64::MAX as u128;
+
+// Full 128x128 -> 256 bit multiplication, returned as (hi, lo).
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let (a_hi, a_lo) = (a >> 64, a & MASK_64);
+    let (b_hi, b_lo) = (b >> 64, b & MASK_64);
+
+    let ll = a_lo * b_lo;
+    let lh = a_lo * b_hi;
+    let hl = a_hi * b_lo;
+    let hh = a_hi * b_hi;
+
+    let mid = (ll >> 64) + (lh & MASK_64) + (hl & MASK_64);
+    let lo = (ll & MASK_64) | (mid << 64);
+    let hi = hh + (lh >> 64) + (hl >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+/// Computes `floor(a * b / c)` using a 256-bit intermediate, or `None` if
+/// `c` is zero or the result does not fit in a u128.
+pub fn checked_mul_div(a: u128, b: u128, c: u128) -> Option<u128> {
+    if c == 0 {
+        return None;
+    }
+
+    let (hi, lo) = full_mul(a, b);
+    if hi == 0 {
+        return Some(lo / c);
+    }
+    if hi >= c {
+        return None;
+    }
+
+    // Schoolbook long division of the 256-bit product, one bit at a time
+    let mut rem = hi;
+    let mut quotient = 0u128;
+    for i in (0..128).rev() {
+        let carry = rem >> 127;
+        rem = (rem << 1) | ((lo >> i) & 1);
+        quotient <<= 1;
+        if carry == 1 || rem >= c {
+            rem = rem.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+/// Computes `floor(a * b / c)` using a 256-bit intermediate.
+///
+/// Panics if `c` is zero or the result does not fit in a u128.
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    assert!(c != 0, "fixed-point division by zero");
+    checked_mul_div(a, b, c).expect("fixed-point overflow")
+}
+
+/// Fixed-point multiplication, rounded down. `None` on overflow.
+pub fn checked_mul(a: u128, b: u128) -> Option<u128> {
+    checked_mul_div(a, b, DECIMALS)
+}
+
+/// Fixed-point division, rounded down. `None` on overflow or division by zero.
+pub fn checked_div(a: u128, b: u128) -> Option<u128> {
+    checked_mul_div(a, DECIMALS, b)
+}
+
+/// Fixed-point multiplication, rounded down.
+pub fn mul(a: u128, b: u128) -> u128 {
+    mul_div(a, b, DECIMALS)
+}
+
+/// Fixed-point division, rounded down.
+pub fn div(a: u128, b: u128) -> u128 {
+    mul_div(a, DECIMALS, b)
+}
+
+// Integer square root, rounded down.
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+
+    let bits = 128 - n.leading_zeros();
+    let mut x = 1u128 << bits.div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Fixed-point square root, rounded down.
+pub fn sqrt(x: u128) -> u128 {
+    // sqrt(x / 1e18) * 1e18 == sqrt(x * 1e18). When x * 1e18 overflows we
+    // pre-scale by the largest even power of ten that fits and make up the
+    // difference with an exact power of ten afterwards.
+    let mut scale = DECIMALS;
+    let mut post = 1u128;
+    while x.checked_mul(scale).is_none() {
+        scale /= 100;
+        post *= 10;
+    }
+    isqrt(x * scale) * post
+}
+
+// Splits x (scaled by 1e36) into k * ln(2) + r and returns (exp(r), k),
+// with exp(r) also scaled by 1e36 so the series loses no precision.
+fn exp_reduced(x_e36: u128) -> (u128, u32) {
+    let k = x_e36 / LN_2_E36;
+    let r = x_e36 - k * LN_2_E36;
+
+    // Taylor series for exp(r), 0 <= r < ln(2)
+    let mut sum = E36;
+    let mut term = E36;
+    let mut n = 1u128;
+    loop {
+        term = mul_div(term, r, n * E36);
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        n += 1;
+    }
+    (sum, k as u32)
+}
+
+/// Fixed-point natural exponential, rounded down, or `None` if the result
+/// does not fit in a u128 (x above roughly 47.2).
+pub fn checked_exp(x: i128) -> Option<u128> {
+    if x < EXP_MIN {
+        return Some(0);
+    }
+
+    let x_e36 = x.unsigned_abs().checked_mul(DECIMALS)?;
+    let (exp_r, k) = exp_reduced(x_e36);
+
+    if x < 0 {
+        // exp(-y) = 1 / (exp(r) * 2^k)
+        Some(mul_div(E36, DECIMALS, exp_r) >> k)
+    } else if k < 128 {
+        checked_mul_div(exp_r, 1 << k, DECIMALS)
+    } else {
+        None
+    }
+}
+
+/// Fixed-point natural exponential, rounded down.
+///
+/// Panics if the result does not fit in a u128 (x above roughly 47.2).
+pub fn exp(x: i128) -> u128 {
+    checked_exp(x).expect("fixed-point exp overflow")
+}
+
+/// Fixed-point natural logarithm, rounded down.
+///
+/// Panics if `x` is zero.
+pub fn ln(x: u128) -> i128 {
+    assert!(x != 0, "fixed-point ln of zero");
+
+    // Normalize x = m * 2^k with 1 <= m < 2, keeping m at 1e36 scale
+    let k = (127 - x.leading_zeros() as i32) - (127 - DECIMALS.leading_zeros() as i32);
+    let mut k = k as i128;
+    let mut m = if k >= 0 {
+        mul_div(x, DECIMALS, 1u128 << k)
+    } else {
+        (x * DECIMALS) << -k
+    };
+    if m >= 2 * E36 {
+        m /= 2;
+        k += 1;
+    } else if m < E36 {
+        m *= 2;
+        k -= 1;
+    }
+
+    // ln(m) = 2 * atanh(s) with s = (m - 1) / (m + 1) in [0, 1/3)
+    let s = mul_div(m - E36, E36, m + E36);
+    let s2 = mul_div(s, s, E36);
+    let mut sum = s;
+    let mut power = s;
+    let mut n = 3u128;
+    loop {
+        power = mul_div(power, s2, E36);
+        let term = power / n;
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        n += 2;
+    }
+
+    let ln_e36 = (2 * sum) as i128 + k * LN_2_E36 as i128;
+    ln_e36.div_euclid(DECIMALS as i128)
+}
+
+/// Formats a fixed-point value as an exact decimal string, without trailing
+/// zeros: `1_500_000_000_000_000_000` becomes `"1.5"`.
+pub fn to_decimal_string(x: u128) -> String {
+    let (whole, frac) = (x / DECIMALS, x % DECIMALS);
+    if frac == 0 {
+        return whole.to_string();
+    }
+    let frac = format!("{:018}", frac);
+    format!("{}.{}", whole, frac.trim_end_matches('0'))
+}
@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod tests {
+    use super::super::fixed::*;
+    use super::super::types::DECIMALS;
+
+    fn to_f64(x: u128) -> f64 {
+        x as f64 / DECIMALS as f64
+    }
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul(2 * DECIMALS, 3 * DECIMALS), 6 * DECIMALS);
+        assert_eq!(div(DECIMALS, 3 * DECIMALS), 333_333_333_333_333_333);
+        assert_eq!(div(6 * DECIMALS, 4 * DECIMALS), 1_500_000_000_000_000_000);
+
+        // The intermediate product needs more than 128 bits
+        let big = u128::MAX / 7;
+        assert_eq!(mul_div(big, 1 << 100, 1 << 100), big);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "fixed-point overflow")]
+    fn test_mul_overflow() {
+        mul(u128::MAX, 2 * DECIMALS);
+    }
+
+    #[test]
+    fn test_checked_ops() {
+        assert_eq!(checked_mul(2 * DECIMALS, 3 * DECIMALS), Some(6 * DECIMALS));
+        assert_eq!(checked_mul(u128::MAX, 2 * DECIMALS), None);
+        assert_eq!(checked_div(DECIMALS, 0), None);
+        assert_eq!(checked_div(u128::MAX, DECIMALS / 2), None);
+        assert_eq!(checked_mul_div(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+
+        assert_eq!(checked_exp(DECIMALS as i128), Some(exp(DECIMALS as i128)));
+        assert_eq!(checked_exp(-100 * DECIMALS as i128), Some(0));
+        assert_eq!(checked_exp(50 * DECIMALS as i128), None);
+        assert_eq!(checked_exp(i128::MAX), None);
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(sqrt(0), 0);
+        assert_eq!(sqrt(DECIMALS), DECIMALS);
+        assert_eq!(sqrt(4 * DECIMALS), 2 * DECIMALS);
+        assert_eq!(sqrt(2 * DECIMALS), 1_414_213_562_373_095_048);
+
+        // Large inputs take the pre-scaled path
+        assert_eq!(sqrt(10_000 * DECIMALS), 100 * DECIMALS);
+        assert_eq!(sqrt(10u128.pow(36)), 10u128.pow(27));
+    }
+
+    #[test]
+    fn test_exp() {
+        assert_eq!(exp(0), DECIMALS);
+        assert_eq!(exp(DECIMALS as i128), 2_718_281_828_459_045_235);
+        assert_eq!(exp(2 * DECIMALS as i128), 7_389_056_098_930_650_227);
+        assert_eq!(exp(-(DECIMALS as i128)), 367_879_441_171_442_321);
+
+        for x in [-20.0, -5.5, -0.25, 0.1, 1.5, 10.0, 30.0, 45.0] {
+            let fixed = to_f64(exp((x * DECIMALS as f64) as i128));
+            let expected = libm::exp(x);
+            // Relative error bound, plus one ulp for tiny results
+            let tolerance = expected * 1e-12 + 1e-18;
+            assert!((fixed - expected).abs() < tolerance, "exp({}) = {}", x, fixed);
+        }
+
+        // Far below the representable range
+        assert_eq!(exp(-100 * DECIMALS as i128), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow")]
+    fn test_exp_overflow() {
+        exp(50 * DECIMALS as i128);
+    }
+
+    #[test]
+    fn test_ln() {
+        assert_eq!(ln(DECIMALS), 0);
+        assert_eq!(ln(2 * DECIMALS), 693_147_180_559_945_309);
+        assert_eq!(ln(DECIMALS / 2), -693_147_180_559_945_310);
+
+        for x in [1e-9, 0.3, 0.999, 1.001, 3.5, 42.0, 1e12] {
+            let fixed = ln((x * DECIMALS as f64) as u128) as f64 / DECIMALS as f64;
+            assert!((fixed - libm::log(x)).abs() < 1e-12, "ln({}) = {}", x, fixed);
+        }
+    }
+
+    #[test]
+    fn test_exp_ln_round_trip() {
+        for x in [DECIMALS / 10, DECIMALS, 7 * DECIMALS, 12_345 * DECIMALS] {
+            let round_trip = exp(ln(x));
+            let diff = round_trip.abs_diff(x);
+            assert!(diff <= x / 10u128.pow(15), "exp(ln({})) = {}", x, round_trip);
+        }
+    }
+
+    #[test]
+    fn test_to_decimal_string() {
+        assert_eq!(to_decimal_string(0), "0");
+        assert_eq!(to_decimal_string(DECIMALS), "1");
+        assert_eq!(to_decimal_string(1_500_000_000_000_000_000), "1.5");
+        assert_eq!(to_decimal_string(540_000_000_000_000_000), "0.54");
+        assert_eq!(to_decimal_string(1), "0.000000000000000001");
+        assert_eq!(to_decimal_string(u128::MAX), "340282366920938463463.374607431768211455");
+    }
+}
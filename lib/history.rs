@@ -0,0 +1,144 @@
+// Timestamped price series for a market, and OHLC candles built from it.
+//
+// A point is recorded after every trade, holding the prices the trade left
+// behind and the collateral it moved. A candle covers one interval-aligned
+// bucket of time: it opens at the price the bucket started with (the
+// previous bucket's close), so consecutive candles join up, and its volume
+// is the collateral traded inside it.
+//
+// Only the most recent points are kept. Once there are more than the limit,
+// the oldest quarter is dropped and the last of them becomes the starting
+// price, so candles after that still open where the market stood.
+use serde::{Deserialize, Serialize};
+
+use crate::types::Price;
+
+/// Points a history keeps unless given another limit: under 10 MB for a
+/// binary market.
+pub const DEFAULT_MAX_POINTS: usize = 100_000;
+
+/// Prices just after one trade.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PricePoint {
+    /// Market clock, in seconds.
+    pub time: u64,
+    /// Price per outcome, indexed by outcome.
+    pub prices: Vec<u128>,
+    /// Collateral paid or refunded by the trade.
+    pub volume: u128,
+}
+
+/// Open, high, low and close prices per outcome over one bucket of time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candle {
+    /// Start of the bucket, a multiple of the interval.
+    pub start: u64,
+    pub open: Vec<u128>,
+    pub high: Vec<u128>,
+    pub low: Vec<u128>,
+    pub close: Vec<u128>,
+    /// Collateral traded in the bucket.
+    pub volume: u128,
+    pub trades: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceHistory {
+    // Prices before the first point kept
+    initial: Vec<u128>,
+    points: Vec<PricePoint>,
+    max_points: usize,
+}
+
+impl PriceHistory {
+    /// An empty history for a market currently at `initial`, keeping up to
+    /// `DEFAULT_MAX_POINTS` points.
+    pub fn new(initial: &Price) -> Self {
+        Self::with_limit(initial, DEFAULT_MAX_POINTS)
+    }
+
+    /// An empty history keeping up to `max_points` points (at least one).
+    pub fn with_limit(initial: &Price, max_points: usize) -> Self {
+        PriceHistory {
+            initial: initial.outcomes.clone(),
+            points: Vec::new(),
+            max_points: max_points.max(1),
+        }
+    }
+
+    /// Records the prices after a trade at `time` that moved `volume`
+    /// collateral. Points stay in time order: a clock that went backwards
+    /// is treated as standing still.
+    pub fn record(&mut self, time: u64, prices: &Price, volume: u128) {
+        let time = self.points.last().map_or(time, |last| time.max(last.time));
+        self.points.push(PricePoint {
+            time,
+            prices: prices.outcomes.clone(),
+            volume,
+        });
+
+        // Drop a quarter at a time, so trimming costs O(1) per point
+        if self.points.len() > self.max_points {
+            let dropped = (self.max_points / 4).max(1);
+            self.initial = self.points[dropped - 1].prices.clone();
+            self.points.drain(..dropped);
+        }
+    }
+
+    pub fn points(&self) -> &[PricePoint] {
+        &self.points
+    }
+
+    /// The prices as of `time`, after every trade before it. Times before
+    /// the oldest point kept get the prices it started from.
+    pub fn price_at(&self, time: u64) -> &[u128] {
+        let before = self.points.partition_point(|point| point.time < time);
+        match before {
+            0 => &self.initial,
+            n => &self.points[n - 1].prices,
+        }
+    }
+
+    /// Candles of `interval` seconds for the trades in `[from, to)`. Buckets
+    /// without trades are left out; their prices are the previous candle's
+    /// close.
+    ///
+    /// Panics if `interval` is zero.
+    pub fn candles(&self, interval: u64, from: u64, to: u64) -> Vec<Candle> {
+        assert!(interval > 0, "candle interval must be positive");
+
+        let first = self.points.partition_point(|point| point.time < from);
+        let last = self.points.partition_point(|point| point.time < to);
+
+        let mut candles: Vec<Candle> = Vec::new();
+        let mut previous = self.price_at(from).to_vec();
+        for point in &self.points[first..last] {
+            let start = point.time - point.time % interval;
+            match candles.last_mut() {
+                Some(candle) if candle.start == start => {
+                    for (i, &price) in point.prices.iter().enumerate() {
+                        candle.high[i] = candle.high[i].max(price);
+                        candle.low[i] = candle.low[i].min(price);
+                    }
+                    candle.close = point.prices.clone();
+                    candle.volume = candle.volume.saturating_add(point.volume);
+                    candle.trades += 1;
+                }
+                _ => {
+                    let open = previous.clone();
+                    candles.push(Candle {
+                        start,
+                        high: open.iter().zip(&point.prices).map(|(a, b)| *a.max(b)).collect(),
+                        low: open.iter().zip(&point.prices).map(|(a, b)| *a.min(b)).collect(),
+                        open,
+                        close: point.prices.clone(),
+                        volume: point.volume,
+                        trades: 1,
+                    });
+                }
+            }
+            previous = point.prices.clone();
+        }
+        candles
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use super::super::history::*;
+    use super::super::types::Price;
+
+    fn price(yes: u128) -> Price {
+        Price { outcomes: vec![yes, 100 - yes] }
+    }
+
+    fn history() -> PriceHistory {
+        let mut history = PriceHistory::new(&price(50));
+        history.record(100, &price(60), 10);
+        history.record(130, &price(40), 5);
+        history.record(150, &price(55), 7);
+        history.record(250, &price(70), 20);
+        history
+    }
+
+    #[test]
+    fn test_candles() {
+        let candles = history().candles(60, 0, 1_000);
+        assert_eq!(candles.len(), 3);
+
+        // 60..120 holds one trade, opening from the initial price
+        assert_eq!(candles[0].start, 60);
+        assert_eq!(candles[0].open, vec![50, 50]);
+        assert_eq!(candles[0].high, vec![60, 50]);
+        assert_eq!(candles[0].low, vec![50, 40]);
+        assert_eq!(candles[0].close, vec![60, 40]);
+        assert_eq!((candles[0].volume, candles[0].trades), (10, 1));
+
+        // 120..180 opens at the previous close
+        assert_eq!(candles[1].start, 120);
+        assert_eq!(candles[1].open, vec![60, 40]);
+        assert_eq!(candles[1].high, vec![60, 60]);
+        assert_eq!(candles[1].low, vec![40, 40]);
+        assert_eq!(candles[1].close, vec![55, 45]);
+        assert_eq!((candles[1].volume, candles[1].trades), (12, 2));
+
+        // 180..240 had no trades and is skipped
+        assert_eq!(candles[2].start, 240);
+        assert_eq!(candles[2].open, vec![55, 45]);
+        assert_eq!(candles[2].close, vec![70, 30]);
+    }
+
+    #[test]
+    fn test_candle_range() {
+        let history = history();
+
+        // Trades before `from` still set the first candle's open
+        let candles = history.candles(60, 140, 250);
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0].open, vec![40, 60]);
+        assert_eq!(candles[0].close, vec![55, 45]);
+        assert_eq!(candles[0].trades, 1);
+
+        assert!(history.candles(60, 300, 400).is_empty());
+        assert_eq!(history.candles(1_000, 0, 1_000).len(), 1);
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let mut history = PriceHistory::with_limit(&price(50), 8);
+        for i in 0..8 {
+            history.record(i * 10, &price(51 + i as u128), 1);
+        }
+        assert_eq!(history.points().len(), 8);
+
+        // The ninth point drops the oldest two, which become the start
+        history.record(80, &price(60), 1);
+        assert_eq!(history.points().len(), 7);
+        assert_eq!(history.points()[0].time, 20);
+        assert_eq!(history.price_at(0), &[52, 48]);
+
+        // Candles over the dropped range open from there
+        let candles = history.candles(1_000, 0, 1_000);
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0].open, vec![52, 48]);
+        assert_eq!(candles[0].close, vec![60, 40]);
+        assert_eq!(candles[0].trades, 7);
+
+        // It stays bounded however many trades follow
+        for i in 0..1_000 {
+            history.record(100 + i, &price(50), 1);
+        }
+        assert!(history.points().len() <= 8);
+    }
+
+    #[test]
+    fn test_clock_going_backwards() {
+        let mut history = PriceHistory::new(&price(50));
+        history.record(100, &price(60), 1);
+        history.record(90, &price(70), 1);
+        assert_eq!(history.points()[1].time, 100);
+        assert_eq!(history.price_at(100), &[50, 50]);
+        assert_eq!(history.price_at(101), &[70, 30]);
+    }
+}
@@ -0,0 +1,223 @@
+// Append-only journal of accepted market operations.
+//
+// Each record is one line: the CRC-32 of the JSON payload as 8 hex digits,
+// a space, the JSON-encoded `Entry`, and a newline. Replaying the records in
+// order rebuilds the engine exactly, because the engine is deterministic.
+//
+// A crash can leave the final record half written, without its newline;
+// `Journal::open` drops it and truncates the file so new records append
+// cleanly. Any complete record that fails to decode, the last included, was
+// once written in full, so the file was damaged and is reported as corrupt.
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::liquidity::LiquidityFunction;
+use crate::market::MarketEngine;
+use crate::types::{TradeError, TraderId};
+
+/// One accepted state change. Trades record the exact shares and collateral
+/// so replay can check that it reproduces the original result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entry {
+    /// Advances the market clock (`MarketEngine::set_time`), so replay sees
+    /// the same close time and time-dependent liquidity as the original.
+    Clock { now: u64 },
+    Deposit { trader: TraderId, amount: u128 },
+    Withdraw { trader: TraderId, amount: u128 },
+    Buy { trader: TraderId, outcome: usize, shares: u128, cost: u128 },
+    Sell { trader: TraderId, outcome: usize, shares: u128, refund: u128 },
+    Close,
+    Resolve { outcome: usize },
+    Invalidate,
+    /// Takes collected fees out of the market (`MarketEngine::withdraw_fees`).
+    WithdrawFees { amount: u128 },
+}
+
+/// When appended records are forced to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// After every record; nothing acknowledged is lost in a crash.
+    Always,
+    /// After every `n` records; a crash can lose up to `n - 1` of them.
+    Every(u32),
+    /// Never; flushing is left to the operating system.
+    Never,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = ();
+
+    /// Parses `always`, `never`, or a record count for `Every`.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "always" => Ok(FsyncPolicy::Always),
+            "never" => Ok(FsyncPolicy::Never),
+            _ => match s.parse() {
+                Ok(0) | Err(_) => Err(()),
+                Ok(n) => Ok(FsyncPolicy::Every(n)),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    /// Record `line` (counting from 1) is damaged and is not a torn final
+    /// write.
+    Corrupt { line: usize },
+    /// Replaying record `line` was rejected by the engine.
+    Rejected { line: usize, error: TradeError },
+    /// Replaying record `line` gave a different cost or refund than recorded.
+    Diverged { line: usize },
+}
+
+impl From<io::Error> for JournalError {
+    fn from(error: io::Error) -> Self {
+        JournalError::Io(error)
+    }
+}
+
+pub struct Journal {
+    file: File,
+    fsync: FsyncPolicy,
+    unsynced: u32,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating it if needed, and returns it
+    /// along with every intact record already in it.
+    pub fn open(path: impl AsRef<Path>, fsync: FsyncPolicy) -> Result<(Self, Vec<Entry>), JournalError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        let mut entries = Vec::new();
+        let mut valid = 0;
+        let mut lines = data.split_inclusive(|&byte| byte == b'\n').peekable();
+        while let Some(line) = lines.next() {
+            match decode(line) {
+                Some(entry) => {
+                    entries.push(entry);
+                    valid += line.len();
+                }
+                // A torn final record from a crash mid-write
+                None if lines.peek().is_none() && !line.ends_with(b"\n") => break,
+                None => return Err(JournalError::Corrupt { line: entries.len() + 1 }),
+            }
+        }
+
+        if valid < data.len() {
+            file.set_len(valid as u64)?;
+            file.sync_all()?;
+        }
+
+        let journal = Journal {
+            file,
+            fsync,
+            unsynced: 0,
+        };
+        Ok((journal, entries))
+    }
+
+    /// Appends one record, syncing it to disk as the policy requires.
+    pub fn append(&mut self, entry: &Entry) -> io::Result<()> {
+        // A single write per record, so a crash tears at most the last one
+        self.file.write_all(encode(entry).as_bytes())?;
+
+        match self.fsync {
+            FsyncPolicy::Always => self.file.sync_data(),
+            FsyncPolicy::Every(n) => {
+                self.unsynced += 1;
+                if self.unsynced >= n {
+                    self.unsynced = 0;
+                    self.file.sync_data()?;
+                }
+                Ok(())
+            }
+            FsyncPolicy::Never => Ok(()),
+        }
+    }
+
+    /// Forces every appended record to disk, whatever the policy.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.unsynced = 0;
+        self.file.sync_data()
+    }
+}
+
+fn encode(entry: &Entry) -> String {
+    let json = serde_json::to_string(entry).expect("journal entries always serialize");
+    format!("{:08x} {}\n", crc32fast::hash(json.as_bytes()), json)
+}
+
+fn decode(line: &[u8]) -> Option<Entry> {
+    let line = std::str::from_utf8(line.strip_suffix(b"\n")?).ok()?;
+    let (checksum, json) = line.split_once(' ')?;
+    if u32::from_str_radix(checksum, 16).ok()? != crc32fast::hash(json.as_bytes()) {
+        return None;
+    }
+    serde_json::from_str(json).ok()
+}
+
+/// Replays `entries` into `engine` in order, stopping at the first record
+/// that fails or does not reproduce its recorded result.
+pub fn replay<L: LiquidityFunction>(
+    engine: &mut MarketEngine<L>,
+    entries: &[Entry],
+) -> Result<(), JournalError> {
+    replay_with(engine, entries, |_, _| {})
+}
+
+/// Like `replay`, but calls `observe` with the engine after each record is
+/// applied, e.g. to rebuild state derived from the trades.
+pub fn replay_with<L: LiquidityFunction>(
+    engine: &mut MarketEngine<L>,
+    entries: &[Entry],
+    mut observe: impl FnMut(&MarketEngine<L>, &Entry),
+) -> Result<(), JournalError> {
+    for (index, entry) in entries.iter().enumerate() {
+        let line = index + 1;
+        match apply(engine, entry) {
+            Ok(true) => observe(engine, entry),
+            Ok(false) => return Err(JournalError::Diverged { line }),
+            Err(error) => return Err(JournalError::Rejected { line, error }),
+        }
+    }
+    Ok(())
+}
+
+// Applies one entry; `Ok(false)` means the engine accepted it but charged or
+// refunded a different amount than recorded.
+fn apply<L: LiquidityFunction>(engine: &mut MarketEngine<L>, entry: &Entry) -> Result<bool, TradeError> {
+    match entry {
+        Entry::Clock { now } => {
+            engine.set_time(*now);
+            Ok(true)
+        }
+        Entry::Deposit { trader, amount } => engine.deposit(trader, *amount).map(|_| true),
+        Entry::Withdraw { trader, amount } => engine.withdraw(trader, *amount).map(|_| true),
+        Entry::Buy { trader, outcome, shares, cost } => {
+            let receipt = engine.buy(trader, *outcome, *shares, None)?;
+            Ok(receipt.collateral == *cost)
+        }
+        Entry::Sell { trader, outcome, shares, refund } => {
+            let receipt = engine.sell(trader, *outcome, *shares, None)?;
+            Ok(receipt.collateral == *refund)
+        }
+        Entry::Close => engine.close().map(|_| true),
+        Entry::Resolve { outcome } => engine.resolve(*outcome).map(|_| true),
+        Entry::Invalidate => engine.invalidate().map(|_| true),
+        Entry::WithdrawFees { amount } => engine.withdraw_fees(*amount).map(|_| true),
+    }
+}
@@ -0,0 +1,223 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::super::fees::FeeSchedule;
+    use super::super::journal::*;
+    use super::super::market::*;
+    use super::super::types::*;
+
+    // A fresh journal path per test, so tests can run in parallel
+    fn journal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lslmsr-{}-{}.journal", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    // Trades against `market`, journaling each accepted operation
+    fn trade(market: &mut MarketEngine, journal: &mut Journal) {
+        market.deposit("alice", 1_000 * DECIMALS).unwrap();
+        journal
+            .append(&Entry::Deposit { trader: "alice".to_string(), amount: 1_000 * DECIMALS })
+            .unwrap();
+
+        let receipt = market.buy("alice", Outcome::Yes, 60 * DECIMALS, None).unwrap();
+        journal
+            .append(&Entry::Buy {
+                trader: receipt.trader,
+                outcome: receipt.outcome,
+                shares: receipt.shares,
+                cost: receipt.collateral,
+            })
+            .unwrap();
+
+        let receipt = market.sell("alice", Outcome::Yes, 20 * DECIMALS, None).unwrap();
+        journal
+            .append(&Entry::Sell {
+                trader: receipt.trader,
+                outcome: receipt.outcome,
+                shares: receipt.shares,
+                refund: receipt.collateral,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_replay_rebuilds_market() {
+        let path = journal_path("replay");
+        let mut market = MarketEngine::new(DECIMALS);
+        let (mut journal, entries) = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        assert!(entries.is_empty());
+
+        trade(&mut market, &mut journal);
+        market.close().unwrap();
+        journal.append(&Entry::Close).unwrap();
+        market.resolve(Outcome::Yes).unwrap();
+        journal.append(&Entry::Resolve { outcome: 0 }).unwrap();
+        drop(journal);
+
+        let (_, entries) = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(entries.len(), 5);
+
+        let mut restored = MarketEngine::new(DECIMALS);
+        replay(&mut restored, &entries).unwrap();
+        assert_eq!(restored.snapshot(), market.snapshot());
+
+        // The observer sees the engine after each record
+        let mut restored = MarketEngine::new(DECIMALS);
+        let mut sequences = Vec::new();
+        replay_with(&mut restored, &entries, |engine, _| sequences.push(engine.sequence)).unwrap();
+        assert_eq!(sequences, vec![0, 1, 2, 2, 2]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_charges_fees() {
+        let path = journal_path("fees");
+        let fees = FeeSchedule::Flat { bps: 50 };
+        let mut market = MarketEngine::new(DECIMALS);
+        market.fees = fees.clone();
+        let (mut journal, _) = Journal::open(&path, FsyncPolicy::Never).unwrap();
+
+        trade(&mut market, &mut journal);
+        let amount = market.fees_collected / 2;
+        market.withdraw_fees(amount).unwrap();
+        journal.append(&Entry::WithdrawFees { amount }).unwrap();
+        drop(journal);
+
+        // Fees are not journaled; the market's schedule recomputes them
+        let (_, entries) = Journal::open(&path, FsyncPolicy::Never).unwrap();
+        let mut restored = MarketEngine::new(DECIMALS);
+        restored.fees = fees;
+        replay(&mut restored, &entries).unwrap();
+        assert!(restored.fees_collected > 0);
+        assert_eq!(restored.snapshot(), market.snapshot());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_final_record_is_dropped() {
+        let path = journal_path("torn");
+        let mut market = MarketEngine::new(DECIMALS);
+        let (mut journal, _) = Journal::open(&path, FsyncPolicy::Every(2)).unwrap();
+        trade(&mut market, &mut journal);
+        drop(journal);
+
+        // Simulate a crash halfway through writing a fourth record
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"0badf00d {\"deposit\":{\"tra").unwrap();
+        drop(file);
+
+        let (mut journal, entries) = Journal::open(&path, FsyncPolicy::Never).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        // The torn tail was cut off, so new records follow the intact ones
+        journal.append(&Entry::Close).unwrap();
+        drop(journal);
+        let (mut journal, entries) = Journal::open(&path, FsyncPolicy::Never).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[3], Entry::Close);
+        journal.sync().unwrap();
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_damaged_record_is_corrupt() {
+        let path = journal_path("corrupt");
+        let mut market = MarketEngine::new(DECIMALS);
+        let (mut journal, _) = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        trade(&mut market, &mut journal);
+        drop(journal);
+
+        // Flip a digit inside the second record's payload
+        let contents = fs::read_to_string(&path).unwrap();
+        let damaged = contents.replacen("\"outcome\":0", "\"outcome\":1", 1);
+        assert_ne!(contents, damaged);
+        fs::write(&path, damaged).unwrap();
+
+        assert!(matches!(
+            Journal::open(&path, FsyncPolicy::Always),
+            Err(JournalError::Corrupt { line: 2 })
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_damaged_final_record_is_corrupt() {
+        let path = journal_path("corrupt-last");
+        let mut market = MarketEngine::new(DECIMALS);
+        let (mut journal, _) = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        trade(&mut market, &mut journal);
+        drop(journal);
+
+        // The last record was written in full, so this is damage, not a
+        // torn write, and must not be truncated away
+        let contents = fs::read_to_string(&path).unwrap();
+        let damaged = contents.replacen("\"refund\":", "\"refund\":1", 1);
+        assert_ne!(contents, damaged);
+        fs::write(&path, &damaged).unwrap();
+
+        assert!(matches!(
+            Journal::open(&path, FsyncPolicy::Always),
+            Err(JournalError::Corrupt { line: 3 })
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), damaged);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_detects_divergence() {
+        let mut market = MarketEngine::new(DECIMALS);
+        let entries = vec![
+            Entry::Deposit { trader: "alice".to_string(), amount: 100 * DECIMALS },
+            Entry::Buy { trader: "alice".to_string(), outcome: 0, shares: 10 * DECIMALS, cost: 1 },
+        ];
+        assert!(matches!(replay(&mut market, &entries), Err(JournalError::Diverged { line: 2 })));
+
+        let mut market = MarketEngine::new(DECIMALS);
+        let entries = vec![Entry::Sell { trader: "bob".to_string(), outcome: 0, shares: 1, refund: 0 }];
+        assert!(matches!(
+            replay(&mut market, &entries),
+            Err(JournalError::Rejected { line: 1, error: TradeError::InsufficientPosition })
+        ));
+    }
+
+    #[test]
+    fn test_replay_restores_clock() {
+        // Resolving only works once the clock has passed the close time
+        let entries = vec![
+            Entry::Deposit { trader: "alice".to_string(), amount: 100 * DECIMALS },
+            Entry::Clock { now: 2_000 },
+            Entry::Resolve { outcome: 1 },
+        ];
+
+        let mut market = MarketEngine::new(DECIMALS);
+        market.close_time = Some(1_000);
+        replay(&mut market, &entries).unwrap();
+        assert_eq!(market.now, 2_000);
+        assert_eq!(market.status, MarketStatus::Resolved(1));
+
+        let mut market = MarketEngine::new(DECIMALS);
+        market.close_time = Some(1_000);
+        assert!(matches!(
+            replay(&mut market, &entries[2..]),
+            Err(JournalError::Rejected { line: 1, error: TradeError::MarketNotClosed })
+        ));
+    }
+
+    #[test]
+    fn test_fsync_policy_from_str() {
+        assert_eq!("always".parse(), Ok(FsyncPolicy::Always));
+        assert_eq!("never".parse(), Ok(FsyncPolicy::Never));
+        assert_eq!("16".parse(), Ok(FsyncPolicy::Every(16)));
+        assert_eq!("0".parse::<FsyncPolicy>(), Err(()));
+        assert_eq!("sometimes".parse::<FsyncPolicy>(), Err(()));
+    }
+}
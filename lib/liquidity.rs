@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fixed;
+use crate::lslmsr::*;
+
+/// Derives the liquidity parameter `b` (and from it, prices) from the
+/// market's outstanding shares. `now` is the market clock in seconds, set
+/// by the caller through `MarketEngine::set_time`. Both return `None` when
+/// a value does not fit in a u128, which the engine reports as
+/// `TradeError::Overflow`.
+pub trait LiquidityFunction {
+    fn b(&self, quantities: &[u128], now: u64) -> Option<u128>;
+
+    /// Marginal prices. Defaults to the LMSR softmax at the current `b`.
+    fn price(&self, quantities: &[u128], now: u64) -> Option<Vec<u128>> {
+        checked_calc_price(quantities, self.b(quantities, now)?)
+    }
+
+    /// The most a market maker can lose over a market's life: the cost of
+    /// the empty market, `b * ln(n)`, at its largest `b`. Defaults to the `b`
+    /// at time 0, which suits liquidity that never grows over time.
+    /// `u128::MAX` if the bound does not fit.
+    fn max_loss(&self, num_outcomes: usize) -> u128 {
+        let empty = vec![0; num_outcomes];
+        match self.b(&empty, 0) {
+            Some(0) => 0,
+            Some(b) => checked_calc_cost(&empty, b).unwrap_or(u128::MAX),
+            None => u128::MAX,
+        }
+    }
+}
+
+impl<L: LiquidityFunction + ?Sized> LiquidityFunction for Box<L> {
+    fn b(&self, quantities: &[u128], now: u64) -> Option<u128> {
+        (**self).b(quantities, now)
+    }
+
+    fn price(&self, quantities: &[u128], now: u64) -> Option<Vec<u128>> {
+        (**self).price(quantities, now)
+    }
+
+    fn max_loss(&self, num_outcomes: usize) -> u128 {
+        (**self).max_loss(num_outcomes)
+    }
+}
+
+/// Classic LMSR with a constant `b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixedLiquidity {
+    pub b: u128,
+}
+
+impl LiquidityFunction for FixedLiquidity {
+    fn b(&self, _quantities: &[u128], _now: u64) -> Option<u128> {
+        Some(self.b)
+    }
+}
+
+/// `b = alpha * sqrt(sum(q))` with softmax prices that sum to exactly 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SqrtLiquidity {
+    pub alpha: u128,
+}
+
+impl LiquidityFunction for SqrtLiquidity {
+    fn b(&self, quantities: &[u128], _now: u64) -> Option<u128> {
+        checked_calc_b(self.alpha, total(quantities)?)
+    }
+}
+
+/// Othman–Pennock LS-LMSR: `b = alpha * sum(q)` with the exact cost
+/// gradient as price. Prices sum to more than 1; the excess (at most
+/// `alpha * n * ln(n)`) is the market maker's vig.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LsLmsrLiquidity {
+    pub alpha: u128,
+}
+
+impl LiquidityFunction for LsLmsrLiquidity {
+    fn b(&self, quantities: &[u128], _now: u64) -> Option<u128> {
+        checked_calc_b_ls(self.alpha, total(quantities)?)
+    }
+
+    fn price(&self, quantities: &[u128], _now: u64) -> Option<Vec<u128>> {
+        checked_calc_price_ls(quantities, self.alpha)
+    }
+}
+
+/// Clamps another liquidity function's `b` to `[min_b, max_b]`. Within the
+/// bounds the inner prices are used; once clamped, `b` is constant and
+/// prices fall back to the softmax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundedLiquidity<L> {
+    pub inner: L,
+    pub min_b: u128,
+    pub max_b: u128,
+}
+
+impl<L: LiquidityFunction> LiquidityFunction for BoundedLiquidity<L> {
+    fn b(&self, quantities: &[u128], now: u64) -> Option<u128> {
+        Some(self.inner.b(quantities, now)?.clamp(self.min_b, self.max_b))
+    }
+
+    fn price(&self, quantities: &[u128], now: u64) -> Option<Vec<u128>> {
+        let inner_b = self.inner.b(quantities, now)?;
+        if (self.min_b..=self.max_b).contains(&inner_b) {
+            self.inner.price(quantities, now)
+        } else {
+            checked_calc_price(quantities, inner_b.clamp(self.min_b, self.max_b))
+        }
+    }
+}
+
+/// Halves another liquidity function's `b` every `half_life` seconds after
+/// `start`, so prices become more decisive as resolution approaches. `b`
+/// tends to zero, so wrap it in `BoundedLiquidity` to keep a floor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "DecayingParams<L>")]
+pub struct DecayingLiquidity<L> {
+    inner: L,
+    start: u64,
+    half_life: u64,
+}
+
+impl<L> DecayingLiquidity<L> {
+    /// `None` if `half_life` is 0, which would decay `b` to nothing at once.
+    pub fn new(inner: L, start: u64, half_life: u64) -> Option<Self> {
+        (half_life > 0).then_some(DecayingLiquidity { inner, start, half_life })
+    }
+
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn half_life(&self) -> u64 {
+        self.half_life
+    }
+}
+
+// A snapshot's decaying liquidity, checked through `DecayingLiquidity::new`.
+#[derive(Deserialize)]
+struct DecayingParams<L> {
+    inner: L,
+    start: u64,
+    half_life: u64,
+}
+
+impl<L> TryFrom<DecayingParams<L>> for DecayingLiquidity<L> {
+    type Error = &'static str;
+
+    fn try_from(params: DecayingParams<L>) -> Result<Self, Self::Error> {
+        DecayingLiquidity::new(params.inner, params.start, params.half_life).ok_or("half_life must be positive")
+    }
+}
+
+impl<L: LiquidityFunction> LiquidityFunction for DecayingLiquidity<L> {
+    fn b(&self, quantities: &[u128], now: u64) -> Option<u128> {
+        let elapsed = now.saturating_sub(self.start) as u128;
+        let exponent = fixed::checked_mul_div(elapsed, fixed::LN_2, self.half_life as u128)?;
+        // Decayed too far for an i128 means decayed to nothing
+        let decay = i128::try_from(exponent).map_or(0, |exponent| fixed::exp(-exponent));
+        fixed::checked_mul(self.inner.b(quantities, now)?, decay)
+    }
+}
+
+// The total outstanding shares, or `None` if they do not fit in a u128.
+fn total(quantities: &[u128]) -> Option<u128> {
+    quantities.iter().try_fold(0u128, |total, &q| total.checked_add(q))
+}
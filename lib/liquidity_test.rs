@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use super::super::liquidity::*;
+    use super::super::lslmsr::*;
+    use super::super::types::DECIMALS;
+
+    #[test]
+    fn test_fixed_liquidity() {
+        let liquidity = FixedLiquidity { b: 42 * DECIMALS };
+        assert_eq!(liquidity.b(&[0, 0], 0), Some(42 * DECIMALS));
+        assert_eq!(liquidity.b(&[500 * DECIMALS, 7 * DECIMALS], 99), Some(42 * DECIMALS));
+    }
+
+    #[test]
+    fn test_sqrt_and_ls_lmsr_liquidity() {
+        let quantities = [60 * DECIMALS, 40 * DECIMALS];
+
+        let sqrt = SqrtLiquidity { alpha: 3 * DECIMALS };
+        assert_eq!(sqrt.b(&quantities, 0), Some(calc_b(3 * DECIMALS, 100 * DECIMALS)));
+        assert_eq!(sqrt.price(&quantities, 0), Some(calc_price(&quantities, 30 * DECIMALS)));
+
+        let ls = LsLmsrLiquidity { alpha: DECIMALS / 10 };
+        assert_eq!(ls.b(&quantities, 0), Some(10 * DECIMALS));
+        assert_eq!(ls.price(&quantities, 0), Some(calc_price_ls(&quantities, DECIMALS / 10)));
+    }
+
+    #[test]
+    fn test_bounded_liquidity() {
+        let bounded = BoundedLiquidity {
+            inner: LsLmsrLiquidity { alpha: DECIMALS / 10 },
+            min_b: 5 * DECIMALS,
+            max_b: 20 * DECIMALS,
+        };
+
+        // Below the floor
+        let small = [10 * DECIMALS, 10 * DECIMALS];
+        assert_eq!(bounded.b(&small, 0), Some(5 * DECIMALS));
+        assert_eq!(bounded.price(&small, 0), Some(calc_price(&small, 5 * DECIMALS)));
+
+        // Within bounds the inner prices are used
+        let medium = [100 * DECIMALS, 50 * DECIMALS];
+        assert_eq!(bounded.b(&medium, 0), Some(15 * DECIMALS));
+        assert_eq!(bounded.price(&medium, 0), Some(calc_price_ls(&medium, DECIMALS / 10)));
+
+        // Above the cap
+        let large = [1_000 * DECIMALS, 500 * DECIMALS];
+        assert_eq!(bounded.b(&large, 0), Some(20 * DECIMALS));
+    }
+
+    #[test]
+    fn test_decaying_liquidity() {
+        let decaying = DecayingLiquidity::new(FixedLiquidity { b: 80 * DECIMALS }, 100, 60).unwrap();
+        let quantities = [DECIMALS, DECIMALS];
+
+        // No decay before the start time
+        assert_eq!(decaying.b(&quantities, 50), Some(80 * DECIMALS));
+        assert_eq!(decaying.b(&quantities, 100), Some(80 * DECIMALS));
+
+        // Halves every half-life (within rounding)
+        let one = decaying.b(&quantities, 160).unwrap();
+        let two = decaying.b(&quantities, 220).unwrap();
+        assert!(one.abs_diff(40 * DECIMALS) < 1_000);
+        assert!(two.abs_diff(20 * DECIMALS) < 1_000);
+
+        // A zero half-life is rejected, also when restoring a snapshot
+        assert_eq!(DecayingLiquidity::new(FixedLiquidity { b: DECIMALS }, 0, 0), None);
+        let json = r#"{"inner":{"b":1},"start":0,"half_life":0}"#;
+        assert!(serde_json::from_str::<DecayingLiquidity<FixedLiquidity>>(json).is_err());
+        let json = serde_json::to_string(&decaying).unwrap();
+        assert_eq!(serde_json::from_str::<DecayingLiquidity<FixedLiquidity>>(&json).unwrap(), decaying);
+    }
+
+    #[test]
+    fn test_max_loss() {
+        // b * ln(n) at the empty market
+        let fixed = FixedLiquidity { b: 42 * DECIMALS };
+        assert_eq!(fixed.max_loss(2), calc_cost(&[0, 0], 42 * DECIMALS));
+        assert!(fixed.max_loss(3) > fixed.max_loss(2));
+
+        // Liquidity that grows from nothing cannot lose
+        assert_eq!(SqrtLiquidity { alpha: DECIMALS }.max_loss(2), 0);
+        assert_eq!(LsLmsrLiquidity { alpha: DECIMALS / 10 }.max_loss(3), 0);
+
+        // unless a floor keeps some
+        let bounded = BoundedLiquidity {
+            inner: LsLmsrLiquidity { alpha: DECIMALS / 10 },
+            min_b: 5 * DECIMALS,
+            max_b: 20 * DECIMALS,
+        };
+        assert_eq!(bounded.max_loss(3), calc_cost(&[0, 0, 0], 5 * DECIMALS));
+    }
+}
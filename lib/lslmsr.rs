@@ -1,18 +1,146 @@
+use crate::fixed;
 use crate::types::DECIMALS;
-use libm::{log, sqrt};
+use libm::log;
+
+// All inputs and outputs are 1e18 fixed-point (see `fixed`). Quantities
+// are indexed by outcome; a binary market is the two-outcome case.
+
+/// The smallest `alpha` for which `calc_b` is positive whenever any shares
+/// are outstanding. One unit of shares (1e-18) has a square root of 1e-9.
+pub const MIN_ALPHA: u128 = 1_000_000_000;
+
+/// The largest `alpha` for which `calc_b` fits in a u128 for every share
+/// total. The square root of `u128::MAX` units is below 1.9e10.
+pub const MAX_ALPHA: u128 = 10_000_000_000 * DECIMALS;
+
+/// `calc_b`, or `None` if `b` does not fit in a u128.
+pub fn checked_calc_b(alpha: u128, total_shares: u128) -> Option<u128> {
+    fixed::checked_mul(alpha, fixed::sqrt(total_shares))
+}
 
 pub fn calc_b(alpha: u128, total_shares: u128) -> u128 {
-    let sqrt_total = sqrt(total_shares as f64);
-    (alpha as f64 * sqrt_total * DECIMALS as f64) as u128 / DECIMALS
+    checked_calc_b(alpha, total_shares).expect("liquidity overflow")
+}
+
+/// `calc_b_ls`, or `None` if `b` does not fit in a u128.
+pub fn checked_calc_b_ls(alpha: u128, total_shares: u128) -> Option<u128> {
+    fixed::checked_mul(alpha, total_shares)
 }
 
-pub fn calc_cost(q_yes: f64, q_no: f64, b: f64) -> f64 {
-    b * log(libm::exp(q_yes / b) + libm::exp(q_no / b))
+/// LS-LMSR liquidity, `b = alpha * sum(q)`.
+pub fn calc_b_ls(alpha: u128, total_shares: u128) -> u128 {
+    checked_calc_b_ls(alpha, total_shares).expect("liquidity overflow")
 }
 
-pub fn calc_price(q_yes: f64, q_no: f64, b: f64) -> (f64, f64) {
-    let exp_yes = libm::exp(q_yes / b); 
-    let exp_no = libm::exp(q_no / b);
-    let denom = exp_yes + exp_no;
-    (exp_yes / denom, exp_no / denom)
-}
\ No newline at end of file
+// Every exponential is taken relative to the largest quantity (the
+// log-sum-exp trick):
+//
+// C(q) = max(q) + b * ln(sum_i e^((q_i - max(q))/b))
+//
+// Each shifted term lies in (0, 1] and the largest is exactly 1, so the
+// sum stays in [1, n] however lopsided the market gets. Terms too small to
+// represent underflow to 0 instead of overflowing.
+fn shifted_exp_terms(quantities: &[u128], b: u128) -> Vec<u128> {
+    let max = quantities.iter().copied().max().unwrap_or(0);
+    quantities
+        .iter()
+        .map(|&q| {
+            fixed::checked_div(max - q, b)
+                .and_then(|x| i128::try_from(x).ok())
+                .map_or(0, |x| fixed::exp(-x))
+        })
+        .collect()
+}
+
+/// `calc_cost`, or `None` if `b` is 0 or the cost does not fit in a u128.
+pub fn checked_calc_cost(quantities: &[u128], b: u128) -> Option<u128> {
+    let max = quantities.iter().copied().max().unwrap_or(0);
+    let sum: u128 = shifted_exp_terms(quantities, b).iter().sum();
+
+    // With b = 0 every term vanishes, and the log of nothing is undefined
+    if b == 0 || sum == 0 {
+        return None;
+    }
+    fixed::checked_mul(b, fixed::ln(sum) as u128)?.checked_add(max)
+}
+
+pub fn calc_cost(quantities: &[u128], b: u128) -> u128 {
+    checked_calc_cost(quantities, b).expect("LMSR cost overflow or zero liquidity")
+}
+
+/// `calc_price`, or `None` if `b` is 0.
+pub fn checked_calc_price(quantities: &[u128], b: u128) -> Option<Vec<u128>> {
+    let terms = shifted_exp_terms(quantities, b);
+    let denom: u128 = terms.iter().sum();
+    let mut prices = terms
+        .iter()
+        .map(|&t| fixed::checked_div(t, denom))
+        .collect::<Option<Vec<u128>>>()?;
+
+    // Rounding dust goes to the last outcome so prices sum to exactly 1
+    let total: u128 = prices.iter().sum();
+    if let Some(last) = prices.last_mut() {
+        *last += DECIMALS - total;
+    }
+    Some(prices)
+}
+
+pub fn calc_price(quantities: &[u128], b: u128) -> Vec<u128> {
+    checked_calc_price(quantities, b).expect("LMSR price with zero liquidity")
+}
+
+/// Exact LS-LMSR prices, the gradient of `calc_cost` with `b = alpha * sum(q)`:
+///
+/// p_i = alpha * ln(sum_j e^(q_j/b))
+///     + (sum_j q_j * e^(q_i/b) - sum_j q_j * e^(q_j/b)) / (sum_j q_j * sum_j e^(q_j/b))
+///
+/// The first (entropy) term is what makes prices sum above 1. It is
+/// evaluated as `alpha * (max(q)/b + ln(sum_j e^((q_j - max(q))/b)))`; the
+/// fraction is unchanged by the shift, so it uses the shifted terms as is.
+pub fn calc_price_ls(quantities: &[u128], alpha: u128) -> Vec<u128> {
+    checked_calc_price_ls(quantities, alpha).expect("LS-LMSR price overflow")
+}
+
+/// `calc_price_ls`, or `None` if an intermediate does not fit or `b` is 0.
+pub fn checked_calc_price_ls(quantities: &[u128], alpha: u128) -> Option<Vec<u128>> {
+    let total = quantities.iter().try_fold(0u128, |total, &q| total.checked_add(q))?;
+    let b = checked_calc_b_ls(alpha, total)?;
+    let max = quantities.iter().copied().max().unwrap_or(0);
+    let terms = shifted_exp_terms(quantities, b);
+    let sum_exp: u128 = terms.iter().sum();
+
+    let log_sum = fixed::checked_div(max, b)?.checked_add(fixed::ln(sum_exp) as u128)?;
+    let entropy = i128::try_from(fixed::checked_mul(alpha, log_sum)?).ok()?;
+    // Each term is at most 1, so q * t <= q and the sum fits like `total`
+    let weighted: u128 = quantities.iter().zip(&terms).map(|(&q, &t)| fixed::mul(q, t)).sum();
+    let weighted = i128::try_from(weighted).ok()?;
+    let denom = fixed::checked_mul(total, sum_exp)?;
+
+    terms
+        .iter()
+        .map(|&t| {
+            let numerator = i128::try_from(fixed::mul(total, t)).ok()? - weighted;
+            let fraction = fixed::checked_mul_div(numerator.unsigned_abs(), DECIMALS, denom)?;
+            let fraction = i128::try_from(fraction).ok()?;
+            let price = if numerator >= 0 {
+                entropy.checked_add(fraction)?
+            } else {
+                entropy - fraction
+            };
+            Some(price.max(0) as u128)
+        })
+        .collect()
+}
+
+// Floating-point reference versions, kept for cross-checking the
+// fixed-point path. Not deterministic across platforms.
+
+pub fn calc_cost_f64(quantities: &[f64], b: f64) -> f64 {
+    b * log(quantities.iter().map(|q| libm::exp(q / b)).sum())
+}
+
+pub fn calc_price_f64(quantities: &[f64], b: f64) -> Vec<f64> {
+    let terms: Vec<f64> = quantities.iter().map(|q| libm::exp(q / b)).collect();
+    let denom: f64 = terms.iter().sum();
+    terms.iter().map(|t| t / denom).collect()
+}
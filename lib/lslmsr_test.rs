@@ -1,47 +1,219 @@
 #[cfg(test)]
 mod tests {
+    use super::super::fixed;
     use super::super::lslmsr::*;
     use super::super::types::DECIMALS;
+    use proptest::prelude::*;
+
+    fn to_f64(x: u128) -> f64 {
+        x as f64 / DECIMALS as f64
+    }
 
     #[test]
     fn test_calc_b() {
         // Test with various alpha and share values
-        assert_eq!(calc_b(100, 0), 0);
-        assert_eq!(calc_b(100, 100), 1000);
-        assert_eq!(calc_b(200, 100), 2000);
-        
+        assert_eq!(calc_b(100 * DECIMALS, 0), 0);
+        assert_eq!(calc_b(100 * DECIMALS, 100 * DECIMALS), 1000 * DECIMALS);
+        assert_eq!(calc_b(200 * DECIMALS, 100 * DECIMALS), 2000 * DECIMALS);
+
         // Test with large values
         let large_alpha = 5000 * DECIMALS;
         let large_shares = 10000 * DECIMALS;
         let result = calc_b(large_alpha, large_shares);
-        assert!(result > 0);
+        assert_eq!(result, 500_000 * DECIMALS);
     }
 
     #[test]
     fn test_calc_cost() {
         // Test with equal q values
-        let b = 1000.0;
-        let cost = calc_cost(100.0, 100.0, b);
+        let b = 1000 * DECIMALS;
+        let cost = calc_cost(&[100 * DECIMALS, 100 * DECIMALS], b);
         // Expected: b * ln(e^(q_yes/b) + e^(q_no/b))
-        let expected = b * libm::log(libm::exp(100.0 / b) + libm::exp(100.0 / b));
-        assert!((cost - expected).abs() < 0.001);
+        let expected = calc_cost_f64(&[100.0, 100.0], 1000.0);
+        assert!((to_f64(cost) - expected).abs() < 1e-9);
 
         // Test with different q values
-        let cost_uneven = calc_cost(200.0, 100.0, b);
+        let cost_uneven = calc_cost(&[200 * DECIMALS, 100 * DECIMALS], b);
         assert!(cost_uneven > cost); // Adding more shares should increase cost
     }
 
     #[test]
     fn test_calc_price() {
         // Test with equal shares (should give 0.5/0.5)
-        let (p_yes, p_no) = calc_price(100.0, 100.0, 1000.0);
-        assert!((p_yes - 0.5).abs() < 0.001);
-        assert!((p_no - 0.5).abs() < 0.001);
-        assert!((p_yes + p_no - 1.0).abs() < 0.001); // Should sum to 1
+        let prices = calc_price(&[100 * DECIMALS, 100 * DECIMALS], 1000 * DECIMALS);
+        let (p_yes, p_no) = (prices[0], prices[1]);
+        assert_eq!(p_yes, DECIMALS / 2);
+        assert_eq!(p_no, DECIMALS / 2);
 
         // Test with uneven shares
-        let (p_yes2, p_no2) = calc_price(200.0, 100.0, 1000.0);
+        let prices2 = calc_price(&[200 * DECIMALS, 100 * DECIMALS], 1000 * DECIMALS);
+        let (p_yes2, p_no2) = (prices2[0], prices2[1]);
         assert!(p_yes2 > p_no2); // More YES shares should give higher YES price
-        assert!((p_yes2 + p_no2 - 1.0).abs() < 0.001); // Should still sum to 1
+        assert_eq!(p_yes2 + p_no2, DECIMALS); // Should still sum to 1
+
+        let f_prices = calc_price_f64(&[200.0, 100.0], 1000.0);
+        assert!((to_f64(p_yes2) - f_prices[0]).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_calc_f64_reference() {
+        let b = 1000.0;
+        let cost = calc_cost_f64(&[100.0, 100.0], b);
+        let expected = b * libm::log(libm::exp(100.0 / b) + libm::exp(100.0 / b));
+        assert!((cost - expected).abs() < 0.001);
+
+        let prices = calc_price_f64(&[100.0, 100.0], b);
+        let (p_yes, p_no) = (prices[0], prices[1]);
+        assert!((p_yes - 0.5).abs() < 0.001);
+        assert!((p_yes + p_no - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_categorical_cost_and_price() {
+        let b = 500 * DECIMALS;
+        let quantities = [300 * DECIMALS, 100 * DECIMALS, 50 * DECIMALS, 0];
+
+        let cost = calc_cost(&quantities, b);
+        let expected = calc_cost_f64(&[300.0, 100.0, 50.0, 0.0], 500.0);
+        assert!((to_f64(cost) - expected).abs() < 1e-9);
+
+        let prices = calc_price(&quantities, b);
+        assert_eq!(prices.len(), 4);
+        assert_eq!(prices.iter().sum::<u128>(), DECIMALS);
+
+        // Prices follow the quantity ordering
+        assert!(prices[0] > prices[1] && prices[1] > prices[2] && prices[2] > prices[3]);
+
+        let f_prices = calc_price_f64(&[300.0, 100.0, 50.0, 0.0], 500.0);
+        for (fixed, float) in prices.iter().zip(f_prices.iter()) {
+            assert!((to_f64(*fixed) - float).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_calc_b_ls() {
+        assert_eq!(calc_b_ls(0, 100 * DECIMALS), 0);
+        assert_eq!(calc_b_ls(DECIMALS / 20, 300 * DECIMALS), 15 * DECIMALS);
+    }
+
+    #[test]
+    fn test_checked_liquidity_overflow() {
+        assert_eq!(checked_calc_b(u128::MAX, 4 * DECIMALS), None);
+        assert_eq!(checked_calc_b_ls(u128::MAX, 2 * DECIMALS), None);
+        assert_eq!(checked_calc_b(DECIMALS, 4 * DECIMALS), Some(2 * DECIMALS));
+
+        // Within the alpha bounds, b fits and stays positive
+        assert!(checked_calc_b(MAX_ALPHA, u128::MAX).is_some());
+        assert_eq!(checked_calc_b(MIN_ALPHA, 1), Some(1));
+        assert_eq!(checked_calc_b(MIN_ALPHA - 1, 1), Some(0));
+
+        // Zero liquidity has no cost or price
+        assert_eq!(checked_calc_cost(&[DECIMALS, 0], 0), None);
+        assert_eq!(checked_calc_cost(&[0, 0], 0), None);
+        assert_eq!(checked_calc_price(&[DECIMALS, 0], 0), None);
+        assert_eq!(checked_calc_price_ls(&[2 * DECIMALS, DECIMALS], u128::MAX / 2), None);
+        assert_eq!(
+            checked_calc_price_ls(&[50 * DECIMALS, 50 * DECIMALS], DECIMALS / 20),
+            Some(calc_price_ls(&[50 * DECIMALS, 50 * DECIMALS], DECIMALS / 20))
+        );
+    }
+
+    #[test]
+    fn test_calc_price_ls() {
+        let alpha = DECIMALS / 20; // 0.05
+        let quantities = [120 * DECIMALS, 80 * DECIMALS, 30 * DECIMALS];
+        let prices = calc_price_ls(&quantities, alpha);
+
+        // The vig pushes the price sum above 1, but never past 1 + alpha * n * ln(n)
+        let sum = to_f64(prices.iter().sum::<u128>());
+        assert!(sum > 1.0);
+        assert!(sum <= 1.0 + 0.05 * 3.0 * libm::log(3.0));
+
+        // Each price is the gradient of the cost function
+        let total: u128 = quantities.iter().sum();
+        let cost = calc_cost(&quantities, calc_b_ls(alpha, total));
+        for i in 0..quantities.len() {
+            let h = DECIMALS / 1000;
+            let mut bumped = quantities;
+            bumped[i] += h;
+            let bumped_cost = calc_cost(&bumped, calc_b_ls(alpha, total + h));
+            let gradient = to_f64(bumped_cost - cost) / to_f64(h);
+            assert!((gradient - to_f64(prices[i])).abs() < 1e-3, "p_{} = {}", i, gradient);
+        }
+    }
+
+    #[test]
+    fn test_calc_price_ls_balanced() {
+        // With equal quantities every price is 1/n plus an equal share of the vig
+        let alpha = DECIMALS / 10;
+        let prices = calc_price_ls(&[50 * DECIMALS, 50 * DECIMALS], alpha);
+        assert_eq!(prices[0], prices[1]);
+
+        let expected = 0.5 + 0.1 * libm::log(2.0);
+        assert!((to_f64(prices[0]) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_extreme_imbalance() {
+        // q/b = 10^6, far past where a naive e^(q/b) overflows
+        let b = DECIMALS;
+        let quantities = [1_000_000 * DECIMALS, 0];
+
+        let cost = calc_cost(&quantities, b);
+        assert_eq!(cost, 1_000_000 * DECIMALS);
+
+        let prices = calc_price(&quantities, b);
+        assert_eq!(prices, vec![DECIMALS, 0]);
+    }
+
+    // Quantities from empty up to 10^12 shares, with a bias towards a
+    // single dominant outcome
+    fn quantities() -> impl Strategy<Value = Vec<u128>> {
+        prop::collection::vec(
+            prop_oneof![0..=1_000 * DECIMALS, 0..=1_000_000_000_000 * DECIMALS],
+            2..=6,
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn prop_cost_is_bounded(q in quantities(), b in DECIMALS / 1_000_000..=1_000_000_000 * DECIMALS) {
+            // max(q) <= C(q) <= max(q) + b * ln(n)
+            let max = *q.iter().max().unwrap();
+            let n = q.len() as u128;
+            let cost = checked_calc_cost(&q, b).unwrap();
+            prop_assert!(cost >= max);
+            prop_assert!(cost <= max + fixed::mul(b, fixed::ln(n * DECIMALS) as u128));
+        }
+
+        #[test]
+        fn prop_prices_form_a_distribution(q in quantities(), b in DECIMALS / 1_000_000..=1_000_000_000 * DECIMALS) {
+            let prices = calc_price(&q, b);
+            prop_assert_eq!(prices.iter().sum::<u128>(), DECIMALS);
+            prop_assert!(prices.iter().all(|&p| p <= DECIMALS));
+
+            // More shares never means a lower price, up to the rounding dust
+            let n = q.len() as u128;
+            for i in 0..q.len() {
+                for j in 0..q.len() {
+                    if q[i] > q[j] {
+                        prop_assert!(prices[i] + n >= prices[j]);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn prop_ls_prices_are_bounded(q in quantities(), alpha in DECIMALS / 1_000..=DECIMALS) {
+            prop_assume!(q.iter().any(|&x| x > 0));
+
+            // Each price is at most 1 + alpha * ln(n), the most the vig can add
+            let n = q.len() as u128;
+            let cap = DECIMALS + fixed::mul(alpha, fixed::ln(n * DECIMALS) as u128) + n;
+            let prices = calc_price_ls(&q, alpha);
+            prop_assert!(prices.iter().all(|&p| p <= cap), "{:?}", prices);
+            prop_assert!(prices.iter().sum::<u128>() >= DECIMALS - n);
+        }
     }
 }
+
@@ -1,180 +1,728 @@
-use crate::lslmsr::*;
+use std::collections::BTreeMap;
+
+use crate::fees::FeeSchedule;
+use crate::fixed;
+use crate::lslmsr::checked_calc_cost;
+use crate::liquidity::*;
 use crate::types::*;
 
-pub struct MarketEngine {
-    pub alpha: u128,
-    pub q_yes: u128,
-    pub q_no: u128,
+/// Version written into every `MarketState` snapshot. Bump it whenever the
+/// snapshot layout changes.
+pub const SNAPSHOT_VERSION: u32 = 5;
+
+pub struct MarketEngine<L = SqrtLiquidity> {
+    pub liquidity: L,
+    pub quantities: Vec<u128>,
     pub total_collateral: u128,
+    pub accounts: BTreeMap<TraderId, Account>,
+    /// Number of trades executed so far.
+    pub sequence: u64,
+    pub status: MarketStatus,
+    /// Trading stops once `now` reaches this time, if set.
+    pub close_time: Option<u64>,
+    /// Market clock in seconds, fed to the liquidity function.
+    pub now: u64,
+    pub limits: TradeLimits,
+    pub fees: FeeSchedule,
+    /// Fees charged so far and not yet withdrawn. Kept apart from
+    /// `total_collateral`, which only backs the shares.
+    pub fees_collected: u128,
+    /// Collateral the operator put up to cover the market maker's loss.
+    /// Trades that would leave it and `total_collateral` short of the
+    /// largest possible payout are refused.
+    pub subsidy: u128,
+    /// What settling the market paid out, once it is resolved or invalid.
+    pub settlement: Option<Settlement>,
 }
 
 impl MarketEngine {
+    /// Creates a binary YES/NO market with `b = alpha * sqrt(sum(q))`.
     pub fn new(alpha: u128) -> Self {
+        Self::with_outcomes(alpha, 2)
+    }
+
+    /// Creates a categorical market with `num_outcomes` mutually exclusive
+    /// outcomes, addressed by index.
+    pub fn with_outcomes(alpha: u128, num_outcomes: usize) -> Self {
+        Self::with_liquidity(SqrtLiquidity { alpha }, num_outcomes)
+    }
+}
+
+impl<L: LiquidityFunction> MarketEngine<L> {
+    /// Creates a market driven by an arbitrary liquidity function.
+    pub fn with_liquidity(liquidity: L, num_outcomes: usize) -> Self {
+        assert!(num_outcomes >= 2, "a market needs at least two outcomes");
+
         Self {
-            alpha,
-            q_yes: 0,
-            q_no: 0,
+            liquidity,
+            quantities: vec![0; num_outcomes],
             total_collateral: 0,
+            accounts: BTreeMap::new(),
+            sequence: 0,
+            status: MarketStatus::Open,
+            close_time: None,
+            now: 0,
+            limits: TradeLimits::default(),
+            fees: FeeSchedule::None,
+            fees_collected: 0,
+            subsidy: 0,
+            settlement: None,
+        }
+    }
+
+    /// Captures the complete engine state so it can be checkpointed and
+    /// later restored with `from_snapshot`.
+    pub fn snapshot(&self) -> MarketState<L>
+    where
+        L: Clone,
+    {
+        MarketState {
+            version: SNAPSHOT_VERSION,
+            liquidity: self.liquidity.clone(),
+            quantities: self.quantities.clone(),
+            total_collateral: self.total_collateral,
+            accounts: self.accounts.clone(),
+            sequence: self.sequence,
+            status: self.status,
+            close_time: self.close_time,
+            now: self.now,
+            limits: self.limits,
+            fees: self.fees.clone(),
+            fees_collected: self.fees_collected,
+            subsidy: self.subsidy,
+            settlement: self.settlement,
+        }
+    }
+
+    /// Rebuilds an engine from a snapshot, rejecting snapshots written by
+    /// another format version or that are internally inconsistent.
+    pub fn from_snapshot(state: MarketState<L>) -> Result<Self, TradeError> {
+        if state.version != SNAPSHOT_VERSION {
+            return Err(TradeError::UnsupportedSnapshot { version: state.version });
+        }
+
+        let n = state.quantities.len();
+        let consistent = n >= 2
+            && state.quantities.iter().try_fold(0u128, |total, &q| total.checked_add(q)).is_some()
+            && state.accounts.values().all(|account| account.positions.len() == n)
+            && !matches!(state.status, MarketStatus::Resolved(index) if index >= n)
+            && state.settlement.is_some() == matches!(state.status, MarketStatus::Resolved(_) | MarketStatus::Invalid)
+            && state.fees.is_valid();
+        if !consistent {
+            return Err(TradeError::InvalidSnapshot);
+        }
+
+        Ok(Self {
+            liquidity: state.liquidity,
+            quantities: state.quantities,
+            total_collateral: state.total_collateral,
+            accounts: state.accounts,
+            sequence: state.sequence,
+            status: state.status,
+            close_time: state.close_time,
+            now: state.now,
+            limits: state.limits,
+            fees: state.fees,
+            fees_collected: state.fees_collected,
+            subsidy: state.subsidy,
+            settlement: state.settlement,
+        })
+    }
+
+    pub fn set_time(&mut self, now: u64) {
+        self.now = now;
+    }
+
+    pub fn num_outcomes(&self) -> usize {
+        self.quantities.len()
+    }
+
+    /// Shares of `outcome` outstanding; 0 for an outcome the market does
+    /// not have.
+    pub fn quantity(&self, outcome: impl Into<usize>) -> u128 {
+        self.quantities.get(outcome.into()).copied().unwrap_or(0)
+    }
+
+    pub fn account(&self, trader: &str) -> Option<&Account> {
+        self.accounts.get(trader)
+    }
+
+    pub fn balance(&self, trader: &str) -> u128 {
+        self.account(trader).map_or(0, |account| account.balance)
+    }
+
+    /// Shares of `outcome` that `trader` holds; 0 for an unknown trader or
+    /// an outcome the market does not have.
+    pub fn position(&self, trader: &str, outcome: impl Into<usize>) -> u128 {
+        let index = outcome.into();
+        self.account(trader)
+            .and_then(|account| account.positions.get(index).copied())
+            .unwrap_or(0)
+    }
+
+    fn account_mut(&mut self, trader: &str) -> &mut Account {
+        let num_outcomes = self.quantities.len();
+        self.accounts
+            .entry(trader.to_string())
+            .or_insert_with(|| Account {
+                balance: 0,
+                positions: vec![0; num_outcomes],
+                volume: 0,
+            })
+    }
+
+    /// Credits collateral to a trader, opening their account if needed.
+    pub fn deposit(&mut self, trader: &str, amount: u128) -> Result<(), TradeError> {
+        let balance = self
+            .balance(trader)
+            .checked_add(amount)
+            .ok_or(TradeError::Overflow)?;
+        self.account_mut(trader).balance = balance;
+        Ok(())
+    }
+
+    /// Debits free collateral from a trader.
+    pub fn withdraw(&mut self, trader: &str, amount: u128) -> Result<(), TradeError> {
+        if self.balance(trader) < amount {
+            return Err(TradeError::InsufficientCollateral);
+        }
+        self.account_mut(trader).balance -= amount;
+        Ok(())
+    }
+
+    fn outcome_index(&self, outcome: impl Into<usize>) -> Result<usize, TradeError> {
+        let index = outcome.into();
+        if index >= self.quantities.len() {
+            return Err(TradeError::InvalidOutcome);
+        }
+        Ok(index)
+    }
+
+    fn check_trade_size(&self, amount: u128) -> Result<(), TradeError> {
+        if amount == 0 {
+            return Err(TradeError::ZeroAmount);
+        }
+        match self.limits.max_trade {
+            Some(max) if amount > max => Err(TradeError::TradeTooLarge { max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes up to `amount` of the collected fees out of the market, e.g. to
+    /// pay the operator.
+    pub fn withdraw_fees(&mut self, amount: u128) -> Result<(), TradeError> {
+        if self.fees_collected < amount {
+            return Err(TradeError::InsufficientCollateral);
         }
+        self.fees_collected -= amount;
+        Ok(())
+    }
+
+    // The fee on trading `shares` for `collateral`. Without a trader, the fee
+    // of one with no volume yet.
+    fn fee(&self, trader: Option<&str>, collateral: u128, shares: u128) -> Result<u128, TradeError> {
+        let volume = trader.and_then(|trader| self.account(trader)).map_or(0, |account| account.volume);
+        self.fees.fee(collateral, shares, volume).ok_or(TradeError::Overflow)
     }
 
-    pub fn buy(&mut self, outcome: Outcome, amount: u128) -> Result<Price, TradeError> {
-        // Calculate cost before the buy
-        let old_cost = if self.q_yes == 0 && self.q_no == 0 {
-            0.0 // If no shares, starting cost is 0
-        } else {
-            calc_cost(
-                self.q_yes as f64,
-                self.q_no as f64,
-                calc_b(self.alpha, self.q_yes + self.q_no) as f64,
-            )
+    /// The most the market maker can lose over the market's life, for its
+    /// liquidity function.
+    pub fn max_loss(&self) -> u128 {
+        self.liquidity.max_loss(self.num_outcomes())
+    }
+
+    /// The market's subsidy, collateral and exposure right now. Once
+    /// settled, what it paid out and lost, and what is left over.
+    pub fn solvency(&self) -> Solvency {
+        // A settled market's loss is realized, and it can lose nothing more
+        let (max_payout, current_loss, max_loss) = match self.settlement {
+            Some(settlement) => (settlement.paid, settlement.loss, 0),
+            None => {
+                let max_payout = self.quantities.iter().copied().max().unwrap_or(0);
+                (max_payout, max_payout.saturating_sub(self.total_collateral), self.max_loss())
+            }
         };
+        Solvency {
+            subsidy: self.subsidy,
+            total_collateral: self.total_collateral,
+            max_payout,
+            current_loss,
+            max_loss,
+        }
+    }
+
+    // Refuses a trade that would leave `quantity` shares of outcome `index`
+    // outstanding with `total_collateral` in the market, if the subsidy
+    // would not cover the shortfall on that or any other outcome winning.
+    fn check_solvency(&self, index: usize, quantity: u128, total_collateral: u128) -> Result<(), TradeError> {
+        let max_payout = self
+            .quantities
+            .iter()
+            .enumerate()
+            .map(|(i, &q)| if i == index { quantity } else { q })
+            .max()
+            .unwrap_or(0);
+        let funds = total_collateral.saturating_add(self.subsidy);
+        if max_payout > funds {
+            return Err(TradeError::Insolvent { shortfall: max_payout - funds });
+        }
+        Ok(())
+    }
+
+    /// Shares of `outcome` the trader may still buy under `max_position`.
+    fn position_room(&self, trader: &str, index: usize) -> u128 {
+        self.limits
+            .max_position
+            .map_or(u128::MAX, |max| max.saturating_sub(self.position(trader, index)))
+    }
+
+    /// The liquidity parameter `b` at the current quantities and time.
+    pub fn current_b(&self) -> Result<u128, TradeError> {
+        self.liquidity.b(&self.quantities, self.now).ok_or(TradeError::Overflow)
+    }
+
+    fn cost_of(&self, quantities: &[u128]) -> Result<u128, TradeError> {
+        // Liquidity functions sum the quantities, so that sum must fit first
+        quantities
+            .iter()
+            .try_fold(0u128, |total, &q| total.checked_add(q))
+            .ok_or(TradeError::Overflow)?;
+
+        let b = self.liquidity.b(quantities, self.now).ok_or(TradeError::Overflow)?;
+
+        // As b goes to 0 the cost tends to max(q), which is 0 for an empty
+        // market under the share-dependent liquidity functions
+        if b == 0 {
+            return Ok(quantities.iter().copied().max().unwrap_or(0));
+        }
+        checked_calc_cost(quantities, b).ok_or(TradeError::Overflow)
+    }
 
-        // Update shares based on outcome
-        match outcome {
-            Outcome::Yes => self.q_yes += amount,
-            Outcome::No => self.q_no += amount,
+    /// Buys `amount` shares of `outcome` for `trader`, charging the cost plus
+    /// the fee. If `max_cost` is set the trade is rejected when the two
+    /// together would come to more than that.
+    pub fn buy(
+        &mut self,
+        trader: &str,
+        outcome: impl Into<usize>,
+        amount: u128,
+        max_cost: Option<u128>,
+    ) -> Result<TradeReceipt, TradeError> {
+        let index = self.outcome_index(outcome)?;
+        self.ensure_open()?;
+        self.check_trade_size(amount)?;
+        if let Some(max) = self.limits.max_position {
+            if amount > self.position_room(trader, index) {
+                return Err(TradeError::PositionLimitExceeded { max });
+            }
         }
 
-        // Calculate new cost
-        let new_cost = calc_cost(
-            self.q_yes as f64,
-            self.q_no as f64,
-            calc_b(self.alpha, self.q_yes + self.q_no) as f64,
-        );
+        // Price the trade before touching any state
+        let cost = self.simulate(index, amount)?;
+        let fee = self.fee(Some(trader), cost, amount)?;
+        let charge = cost.checked_add(fee).ok_or(TradeError::Overflow)?;
+        if let Some(quoted) = max_cost {
+            if charge > quoted {
+                return Err(TradeError::SlippageExceeded { quoted, actual: charge });
+            }
+        }
+        if self.balance(trader) < charge {
+            return Err(TradeError::InsufficientCollateral);
+        }
+
+        // simulate() already checked the new quantity fits
+        let position = self
+            .position(trader, index)
+            .checked_add(amount)
+            .ok_or(TradeError::Overflow)?;
+        let total_collateral = self
+            .total_collateral
+            .checked_add(cost)
+            .ok_or(TradeError::Overflow)?;
+        let fees_collected = self.fees_collected.checked_add(fee).ok_or(TradeError::Overflow)?;
+        self.check_solvency(index, self.quantities[index] + amount, total_collateral)?;
+
+        let mut quantities = self.quantities.clone();
+        quantities[index] += amount;
+        let before = self.state_at(&self.quantities)?;
+        let after = self.state_at(&quantities)?;
 
-        // Calculate cost difference and update total collateral
-        let cost_diff = new_cost - old_cost;
-        let cost_to_add = (cost_diff * DECIMALS as f64) as u128;
-        self.total_collateral += cost_to_add;
+        // Move the trader's collateral into the market and mint the shares
+        let account = self.account_mut(trader);
+        account.balance -= charge;
+        account.positions[index] = position;
+        account.volume = account.volume.saturating_add(cost);
+        self.quantities = quantities;
+        self.total_collateral = total_collateral;
+        self.fees_collected = fees_collected;
 
-        // Get current price after buy
-        Ok(self.get_price())
+        Ok(self.receipt(trader, Side::Buy, index, amount, cost, fee, before, after))
     }
 
-    pub fn get_price(&self) -> Price {
-        // Handle the case where there are no shares
-        if self.q_yes == 0 && self.q_no == 0 {
-            return Price {
-                yes: DECIMALS / 2,
-                no: DECIMALS / 2,
-            };
+    #[allow(clippy::too_many_arguments)]
+    fn receipt(
+        &mut self,
+        trader: &str,
+        side: Side,
+        outcome: usize,
+        shares: u128,
+        collateral: u128,
+        fee: u128,
+        (price_before, b_before): (Price, u128),
+        (price_after, b_after): (Price, u128),
+    ) -> TradeReceipt {
+        self.sequence += 1;
+
+        TradeReceipt {
+            sequence: self.sequence,
+            trader: trader.to_string(),
+            side,
+            outcome,
+            shares,
+            collateral,
+            fee,
+            price_before,
+            price_after,
+            average_price: average_price(collateral, shares),
+            b_before,
+            b_after,
         }
+    }
+
+    // The prices and `b` at `quantities`, so a trade can be priced in full
+    // before it changes anything.
+    fn state_at(&self, quantities: &[u128]) -> Result<(Price, u128), TradeError> {
+        let b = self.liquidity.b(quantities, self.now).ok_or(TradeError::Overflow)?;
+        Ok((self.price_at(quantities)?, b))
+    }
 
-        let (p_yes, p_no) = calc_price(
-            self.q_yes as f64,
-            self.q_no as f64,
-            calc_b(self.alpha, self.q_yes + self.q_no) as f64,
-        );
+    /// Spends at most `max_cost` of the trader's collateral on `outcome`,
+    /// fees included, buying as many shares as it affords within the
+    /// market's limits.
+    pub fn buy_with_collateral(
+        &mut self,
+        trader: &str,
+        outcome: impl Into<usize>,
+        max_cost: u128,
+    ) -> Result<TradeReceipt, TradeError> {
+        let index = self.outcome_index(outcome)?;
+        self.ensure_open()?;
 
-        Price {
-            yes: (p_yes * DECIMALS as f64) as u128,
-            no: (p_no * DECIMALS as f64) as u128,
+        // A budget too small for one share buys nothing, which is refused
+        // rather than recorded as an empty trade
+        let room = self.position_room(trader, index);
+        let shares = self.shares_for(Some(trader), index, max_cost).min(room);
+        match (shares, self.limits.max_position) {
+            (0, Some(max)) if room == 0 => Err(TradeError::PositionLimitExceeded { max }),
+            (0, _) => Err(TradeError::InsufficientCollateral),
+            _ => self.buy(trader, index, shares, Some(max_cost)),
         }
     }
 
-    pub fn simulate(&self, outcome: Outcome, amount: u128) -> u128 {
-        let mut q_yes = self.q_yes;
-        let mut q_no = self.q_no;
+    /// The marginal prices. Once settled, each share is priced at what it
+    /// paid out: all of the collateral for the winner, or `1 / n` when the
+    /// market was invalidated.
+    pub fn get_price(&self) -> Result<Price, TradeError> {
+        match self.status {
+            MarketStatus::Resolved(winner) => {
+                let mut payouts = vec![0; self.quantities.len()];
+                payouts[winner] = 1;
+                Ok(leader_price(&payouts))
+            }
+            MarketStatus::Invalid => Ok(leader_price(&vec![0; self.quantities.len()])),
+            MarketStatus::Open | MarketStatus::Closed => self.price_at(&self.quantities),
+        }
+    }
 
-        match outcome {
-            Outcome::Yes => q_yes += amount,
-            Outcome::No => q_no += amount,
+    fn price_at(&self, quantities: &[u128]) -> Result<Price, TradeError> {
+        let total = quantities
+            .iter()
+            .try_fold(0u128, |total, &q| total.checked_add(q))
+            .ok_or(TradeError::Overflow)?;
+
+        // With no shares every outcome leads. As b goes to 0 the softmax puts
+        // all the weight on the largest quantity, shared between any ties.
+        let b = self.liquidity.b(quantities, self.now).ok_or(TradeError::Overflow)?;
+        if total == 0 || b == 0 {
+            return Ok(leader_price(quantities));
         }
 
-        // Handle the case where there are no initial shares
-        let old_cost = if self.q_yes == 0 && self.q_no == 0 {
-            0.0 // If no shares, starting cost is 0
-        } else {
-            let b_old = calc_b(self.alpha, self.q_yes + self.q_no) as f64;
-            calc_cost(self.q_yes as f64, self.q_no as f64, b_old)
-        };
+        let outcomes = self.liquidity.price(quantities, self.now).ok_or(TradeError::Overflow)?;
+        Ok(Price { outcomes })
+    }
+
+    pub fn simulate(&self, outcome: impl Into<usize>, amount: u128) -> Result<u128, TradeError> {
+        let index = self.outcome_index(outcome)?;
+
+        let mut quantities = self.quantities.clone();
+        quantities[index] = quantities[index]
+            .checked_add(amount)
+            .ok_or(TradeError::Overflow)?;
+
+        // The cost function is increasing, but rounding can put the cost
+        // after a tiny trade at or below the cost before it. Any buy costs
+        // at least one unit.
+        let cost = self.cost_of(&quantities)?.saturating_sub(self.cost_of(&self.quantities)?);
+        Ok(if amount == 0 { 0 } else { cost.max(1) })
+    }
 
-        // Calculate new cost
-        let b_new = calc_b(self.alpha, q_yes + q_no) as f64;
-        let new_cost = calc_cost(q_yes as f64, q_no as f64, b_new);
+    // Whether `trader` can buy `amount` shares for at most `max_cost`, fees
+    // included.
+    fn affordable(&self, trader: Option<&str>, index: usize, amount: u128, max_cost: u128) -> bool {
+        let charge = self.simulate(index, amount).and_then(|cost| {
+            let fee = self.fee(trader, cost, amount)?;
+            cost.checked_add(fee).ok_or(TradeError::Overflow)
+        });
+        matches!(charge, Ok(charge) if charge <= max_cost)
+    }
 
-        // Return cost difference in fixed-point notation
-        ((new_cost - old_cost) * DECIMALS as f64) as u128
+    /// Largest number of shares of `outcome` that can be bought for at most
+    /// `max_cost` collateral, fees included, found by bisection over the
+    /// cost function and capped at `max_trade`. Fees are those of a trader
+    /// with no volume yet.
+    pub fn simulate_with_collateral(&self, outcome: impl Into<usize>, max_cost: u128) -> u128 {
+        self.shares_for(None, outcome.into(), max_cost)
     }
 
-    pub fn simulate_sell(&self, outcome: Outcome, amount: u128) -> Result<u128, TradeError> {
-        // Validate the user has enough shares to sell
-        match outcome {
-            Outcome::Yes if self.q_yes < amount => return Err(TradeError::InsufficientCollateral),
-            Outcome::No if self.q_no < amount => return Err(TradeError::InsufficientCollateral),
-            _ => {}
+    fn shares_for(&self, trader: Option<&str>, index: usize, max_cost: u128) -> u128 {
+        let affordable = |amount| self.affordable(trader, index, amount, max_cost);
+        if let Some(max_trade) = self.limits.max_trade {
+            if affordable(max_trade) {
+                return max_trade;
+            }
+        }
+
+        // Double the upper bound until it costs too much. This terminates
+        // because buying s shares always costs at least s - C(q).
+        // A trade too large to price counts as unaffordable.
+        let mut lo = 0;
+        let mut hi = max_cost.max(1);
+        while affordable(hi) {
+            lo = hi;
+            match hi.checked_mul(2) {
+                Some(next) => hi = next,
+                None => return lo,
+            }
         }
 
-        let mut q_yes = self.q_yes;
-        let mut q_no = self.q_no;
+        // Invariant: lo is affordable, hi is not
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if affordable(mid) {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
 
-        // Calculate cost before the sell
-        let old_cost = calc_cost(
-            q_yes as f64,
-            q_no as f64,
-            calc_b(self.alpha, q_yes + q_no) as f64,
-        );
+    pub fn simulate_sell(&self, outcome: impl Into<usize>, amount: u128) -> Result<u128, TradeError> {
+        let index = self.outcome_index(outcome)?;
 
-        // Subtract the shares
-        match outcome {
-            Outcome::Yes => q_yes -= amount,
-            Outcome::No => q_no -= amount,
+        // Validate there are enough shares to sell
+        if self.quantities[index] < amount {
+            return Err(TradeError::InsufficientPosition);
         }
 
-        // Calculate cost after the sell
-        let new_cost = if q_yes == 0 && q_no == 0 {
-            0.0
-        } else {
-            calc_cost(
-                q_yes as f64,
-                q_no as f64,
-                calc_b(self.alpha, q_yes + q_no) as f64,
-            )
-        };
+        let mut quantities = self.quantities.clone();
+        quantities[index] -= amount;
+
+        // Calculate refund amount; as for buys, a rounding difference that
+        // comes out negative refunds nothing
+        Ok(self.cost_of(&self.quantities)?.saturating_sub(self.cost_of(&quantities)?))
+    }
+
+    /// Prices a buy or sell of `amount` shares of `outcome` without executing
+    /// it. Sells are checked against the market's supply, not any trader's
+    /// position, and buys against `max_trade` but not `max_position`. Both
+    /// are refused if they would leave the market insolvent.
+    pub fn quote(&self, side: Side, outcome: impl Into<usize>, amount: u128) -> Result<Quote, TradeError> {
+        let index = self.outcome_index(outcome)?;
+        self.check_trade_size(amount)?;
 
-        // Calculate refund amount
-        let refund = (old_cost - new_cost) * DECIMALS as f64;
-        Ok(refund as u128)
-    }
-
-    pub fn sell(&mut self, outcome: Outcome, amount: u128) -> Result<Price, TradeError> {
-        // Validate the user has enough shares to sell
-        match outcome {
-            Outcome::Yes if self.q_yes < amount => return Err(TradeError::InsufficientCollateral),
-            Outcome::No if self.q_no < amount => return Err(TradeError::InsufficientCollateral),
-            _ => {}
-        }
-
-        // Calculate cost before the sell
-        let old_cost = calc_cost(
-            self.q_yes as f64,
-            self.q_no as f64,
-            calc_b(self.alpha, self.q_yes + self.q_no) as f64,
-        );
-
-        // Subtract the shares
-        match outcome {
-            Outcome::Yes => self.q_yes -= amount,
-            Outcome::No => self.q_no -= amount,
-        }
-
-        // Calculate cost after the sell
-        let new_cost = if self.q_yes == 0 && self.q_no == 0 {
-            0.0
-        } else {
-            calc_cost(
-                self.q_yes as f64,
-                self.q_no as f64,
-                calc_b(self.alpha, self.q_yes + self.q_no) as f64,
-            )
+        let mut quantities = self.quantities.clone();
+        let (collateral, total_collateral) = match side {
+            Side::Buy => {
+                let cost = self.simulate(index, amount)?;
+                quantities[index] += amount;
+                (cost, self.total_collateral.saturating_add(cost))
+            }
+            Side::Sell => {
+                let refund = self.simulate_sell(index, amount)?;
+                quantities[index] -= amount;
+                (refund, self.total_collateral.saturating_sub(refund))
+            }
         };
+        self.check_solvency(index, quantities[index], total_collateral)?;
+
+        Ok(Quote {
+            side,
+            outcome: index,
+            shares: amount,
+            collateral,
+            fee: self.fee(None, collateral, amount)?,
+            average_price: average_price(collateral, amount),
+            price_before: self.price_at(&self.quantities)?,
+            price_after: self.price_at(&quantities)?,
+        })
+    }
+
+    /// Sells `amount` shares of `outcome` for `trader`, paying out the refund
+    /// less the fee. If `min_refund` is set the trade is rejected when that
+    /// comes to less than it.
+    pub fn sell(
+        &mut self,
+        trader: &str,
+        outcome: impl Into<usize>,
+        amount: u128,
+        min_refund: Option<u128>,
+    ) -> Result<TradeReceipt, TradeError> {
+        let index = self.outcome_index(outcome)?;
+        self.ensure_open()?;
+        self.check_trade_size(amount)?;
+
+        // Validate the trader owns enough shares to sell
+        if self.position(trader, index) < amount {
+            return Err(TradeError::InsufficientPosition);
+        }
+
+        let refund = self.simulate_sell(index, amount)?;
+        let fee = self.fee(Some(trader), refund, amount)?;
+        let payout = refund.checked_sub(fee).ok_or(TradeError::NumericalError)?;
+        if let Some(quoted) = min_refund {
+            if payout < quoted {
+                return Err(TradeError::SlippageExceeded { quoted, actual: payout });
+            }
+        }
+
+        let balance = self
+            .balance(trader)
+            .checked_add(payout)
+            .ok_or(TradeError::Overflow)?;
+        let fees_collected = self.fees_collected.checked_add(fee).ok_or(TradeError::Overflow)?;
+        let total_collateral = self.total_collateral.saturating_sub(refund);
+        self.check_solvency(index, self.quantities[index] - amount, total_collateral)?;
+
+        let mut quantities = self.quantities.clone();
+        quantities[index] -= amount;
+        let before = self.state_at(&self.quantities)?;
+        let after = self.state_at(&quantities)?;
+
+        // Burn the shares and pay the refund out of the market's collateral
+        let account = self.account_mut(trader);
+        account.positions[index] -= amount;
+        account.balance = balance;
+        account.volume = account.volume.saturating_add(refund);
+        self.quantities = quantities;
+        self.total_collateral = total_collateral;
+        self.fees_collected = fees_collected;
+
+        Ok(self.receipt(trader, Side::Sell, index, amount, refund, fee, before, after))
+    }
+
+    /// Whether the market accepts trades right now.
+    pub fn is_open(&self) -> bool {
+        self.status == MarketStatus::Open && self.close_time.is_none_or(|t| self.now < t)
+    }
+
+    fn ensure_open(&self) -> Result<(), TradeError> {
+        if !self.is_open() {
+            return Err(TradeError::MarketClosed);
+        }
+        Ok(())
+    }
+
+    /// Stops trading ahead of resolution.
+    pub fn close(&mut self) -> Result<(), TradeError> {
+        match self.status {
+            MarketStatus::Open => {
+                self.status = MarketStatus::Closed;
+                Ok(())
+            }
+            MarketStatus::Closed => Err(TradeError::MarketClosed),
+            MarketStatus::Resolved(_) | MarketStatus::Invalid => Err(TradeError::MarketSettled),
+        }
+    }
+
+    fn ensure_closed(&self) -> Result<(), TradeError> {
+        match self.status {
+            // A market past its close time is closed even if nobody called close()
+            MarketStatus::Open if !self.is_open() => Ok(()),
+            MarketStatus::Open => Err(TradeError::MarketNotClosed),
+            MarketStatus::Closed => Ok(()),
+            MarketStatus::Resolved(_) | MarketStatus::Invalid => Err(TradeError::MarketSettled),
+        }
+    }
+
+    /// Settles a closed market on `outcome`: every winning share pays out one
+    /// unit of collateral and all positions are redeemed. Returns the total
+    /// paid out.
+    pub fn resolve(&mut self, outcome: impl Into<usize>) -> Result<u128, TradeError> {
+        let index = self.outcome_index(outcome)?;
+        self.ensure_closed()?;
+
+        self.settle(MarketStatus::Resolved(index), |positions| positions[index])
+    }
+
+    /// Settles a closed market as invalid: every share of every outcome is
+    /// refunded `1 / n` units of collateral. Returns the total paid out.
+    pub fn invalidate(&mut self) -> Result<u128, TradeError> {
+        self.ensure_closed()?;
+
+        let n = self.quantities.len() as u128;
+        self.settle(MarketStatus::Invalid, |positions| positions.iter().sum::<u128>() / n)
+    }
+
+    // Credits every account with `payout(positions)`, redeems all shares and
+    // moves the market to `status`. Nothing is changed unless every new
+    // balance fits.
+    fn settle(&mut self, status: MarketStatus, payout: impl Fn(&[u128]) -> u128) -> Result<u128, TradeError> {
+        let mut paid = 0u128;
+        let mut balances = Vec::with_capacity(self.accounts.len());
+        for account in self.accounts.values() {
+            let amount = payout(&account.positions);
+            balances.push(account.balance.checked_add(amount).ok_or(TradeError::Overflow)?);
+            paid = paid.checked_add(amount).ok_or(TradeError::Overflow)?;
+        }
+
+        self.status = status;
+        for (account, balance) in self.accounts.values_mut().zip(balances) {
+            account.balance = balance;
+            account.positions.iter_mut().for_each(|p| *p = 0);
+        }
+        self.quantities.iter_mut().for_each(|q| *q = 0);
+
+        // Any shortfall is the market maker's loss, covered by the subsidy.
+        // Trades were refused before it could outgrow the subsidy, but a
+        // market funded short of that still reports the whole loss.
+        let loss = paid.saturating_sub(self.total_collateral);
+        self.total_collateral = self.total_collateral.saturating_sub(paid);
+        self.subsidy = self.subsidy.saturating_sub(loss);
+        self.settlement = Some(Settlement { paid, loss });
+        Ok(paid)
+    }
+}
 
-        // Reduce collateral
-        let refund = (old_cost - new_cost) * DECIMALS as f64;
-        self.total_collateral = self.total_collateral.saturating_sub(refund as u128);
+// All of the price on the largest quantity, split evenly between ties. Any
+// remainder goes to the first of them, so the prices sum to exactly 1.
+fn leader_price(quantities: &[u128]) -> Price {
+    let max = quantities.iter().copied().max().unwrap_or(0);
+    let leaders = quantities.iter().filter(|&&q| q == max).count() as u128;
+    let mut outcomes: Vec<u128> = quantities
+        .iter()
+        .map(|&q| if q == max { DECIMALS / leaders } else { 0 })
+        .collect();
+    let first = quantities.iter().position(|&q| q == max).unwrap_or(0);
+    outcomes[first] += DECIMALS % leaders;
+    Price { outcomes }
+}
 
-        // Return the updated price
-        Ok(self.get_price())
+// Collateral per share of a trade, or 0 for an empty one.
+fn average_price(collateral: u128, shares: u128) -> u128 {
+    if shares == 0 {
+        0
+    } else {
+        fixed::div(collateral, shares)
     }
-}
\ No newline at end of file
+}
@@ -1,17 +1,29 @@
 #[cfg(test)]
 mod tests {
+    use super::super::fees::*;
+    use super::super::liquidity::*;
+    use super::super::lslmsr::calc_cost;
     use super::super::market::*;
     use super::super::types::*;
-    use super::super::lslmsr::*;
+    use proptest::prelude::*;
+
+    const ALICE: &str = "alice";
+
+    // Gives alice collateral to trade with and covers the market maker's
+    // worst case, so constant-b markets stay solvent
+    fn fund<L: LiquidityFunction>(market: &mut MarketEngine<L>) {
+        market.deposit(ALICE, 1_000_000 * DECIMALS).unwrap();
+        market.subsidy = market.max_loss();
+    }
 
     #[test]
     fn test_market_initialization() {
         let alpha = 100;
         let market = MarketEngine::new(alpha);
         
-        assert_eq!(market.alpha, alpha);
-        assert_eq!(market.q_yes, 0);
-        assert_eq!(market.q_no, 0);
+        assert_eq!(market.liquidity.alpha, alpha);
+        assert_eq!(market.quantity(Outcome::Yes), 0);
+        assert_eq!(market.quantity(Outcome::No), 0);
         assert_eq!(market.total_collateral, 0);
     }
 
@@ -19,88 +31,89 @@ mod tests {
     fn test_initial_price() {
         // With zero shares, we need to handle the special case
         // Initial price with 0 shares should be 0.5/0.5
-        let market = MarketEngine::new(100);
+        let market = MarketEngine::new(100 * DECIMALS);
         
         // Get the actual price
-        let price = market.get_price();
+        let price = market.get_price().unwrap();
         
         // Print debug values to understand what's happening
-        println!("Price yes: {}, Price no: {}", price.yes, price.no);
+        println!("Price yes: {}, Price no: {}", price.yes(), price.no());
         
         // For initial price, we just check they're equal and sum to DECIMALS
-        assert_eq!(price.yes, price.no); // Both should be equal
-        assert_eq!(price.yes + price.no, DECIMALS); // Should sum to DECIMALS
+        assert_eq!(price.yes(), price.no()); // Both should be equal
+        assert_eq!(price.yes() + price.no(), DECIMALS); // Should sum to DECIMALS
         
         // For safety, also check they're close to 50%
         let half_decimals = DECIMALS / 2;
         let tolerance = DECIMALS / 100; // 1% tolerance
         
-        assert!((price.yes as i128 - half_decimals as i128).abs() < tolerance as i128);
-        assert!((price.no as i128 - half_decimals as i128).abs() < tolerance as i128);
+        assert!((price.yes() as i128 - half_decimals as i128).abs() < tolerance as i128);
+        assert!((price.no() as i128 - half_decimals as i128).abs() < tolerance as i128);
     }
 
     #[test]
     fn test_buy_shares() {
-        let mut market = MarketEngine::new(100);
-        let initial_collateral = market.total_collateral;
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
         
         // First, add some initial liquidity by buying both sides equally
         // This helps avoid the zero-share edge case
-        market.buy(Outcome::Yes, 10).unwrap();
-        market.buy(Outcome::No, 10).unwrap();
+        market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+        market.buy(ALICE, Outcome::No, 10 * DECIMALS, None).unwrap();
         
         // Reset the initial collateral for our test
         let initial_collateral = market.total_collateral;
         
         // Buy YES shares
-        let result = market.buy(Outcome::Yes, 100);
+        let result = market.buy(ALICE, Outcome::Yes, 100 * DECIMALS, None);
         assert!(result.is_ok());
-        let price = result.unwrap();
+        let price = result.unwrap().price_after;
         
         // Verify state changes
-        assert_eq!(market.q_yes, 110); // 10 initial + 100 new
-        assert_eq!(market.q_no, 10);
+        assert_eq!(market.quantity(Outcome::Yes), 110 * DECIMALS); // 10 initial + 100 new
+        assert_eq!(market.quantity(Outcome::No), 10 * DECIMALS);
         assert!(market.total_collateral > initial_collateral, 
                 "Collateral should increase when buying shares. Was {} now {}", 
                 initial_collateral, market.total_collateral);
         
         // Prices should reflect the buy (YES price should be higher)
-        assert!(price.yes > price.no);
+        assert!(price.yes() > price.no());
         
         // Buy NO shares to rebalance
-        let result2 = market.buy(Outcome::No, 100);
+        let result2 = market.buy(ALICE, Outcome::No, 100 * DECIMALS, None);
         assert!(result2.is_ok());
-        let price2 = result2.unwrap();
+        let price2 = result2.unwrap().price_after;
         
         // Prices should be closer now
-        let price_diff = (price2.yes as i128 - price2.no as i128).abs();
-        let initial_diff = (price.yes as i128 - price.no as i128).abs();
+        let price_diff = (price2.yes() as i128 - price2.no() as i128).abs();
+        let initial_diff = (price.yes() as i128 - price.no() as i128).abs();
         assert!(price_diff < initial_diff);
     }
 
     #[test]
     fn test_simulate_buy() {
         // Create market with some initial liquidity to avoid zero-share edge case
-        let mut market = MarketEngine::new(100);
-        market.buy(Outcome::Yes, 10).unwrap();
-        market.buy(Outcome::No, 10).unwrap();
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+        market.buy(ALICE, Outcome::No, 10 * DECIMALS, None).unwrap();
         
         // Store the current state
-        let initial_yes = market.q_yes;
-        let initial_no = market.q_no;
+        let initial_yes = market.quantity(Outcome::Yes);
+        let initial_no = market.quantity(Outcome::No);
         let initial_collateral = market.total_collateral;
         
         // Simulate buying YES shares
-        let cost = market.simulate(Outcome::Yes, 100);
+        let cost = market.simulate(Outcome::Yes, 100 * DECIMALS).unwrap();
         assert!(cost > 0, "Simulated cost should be positive, was {}", cost);
         
         // The actual market state should remain unchanged
-        assert_eq!(market.q_yes, initial_yes);
-        assert_eq!(market.q_no, initial_no);
+        assert_eq!(market.quantity(Outcome::Yes), initial_yes);
+        assert_eq!(market.quantity(Outcome::No), initial_no);
         assert_eq!(market.total_collateral, initial_collateral);
         
         // Now do the actual buy
-        market.buy(Outcome::Yes, 100).unwrap();
+        market.buy(ALICE, Outcome::Yes, 100 * DECIMALS, None).unwrap();
         
         // The cost from simulate should be close to the actual cost
         let actual_cost = market.total_collateral - initial_collateral;
@@ -115,99 +128,103 @@ mod tests {
     #[test]
     fn test_edge_cases() {
         // Test with zero shares (edge case)
-        let market = MarketEngine::new(100);
+        let market = MarketEngine::new(100 * DECIMALS);
         
         // Get price
-        let price = market.get_price();
-        assert_eq!(price.yes, DECIMALS / 2);
-        assert_eq!(price.no, DECIMALS / 2);
+        let price = market.get_price().unwrap();
+        assert_eq!(price.yes(), DECIMALS / 2);
+        assert_eq!(price.no(), DECIMALS / 2);
         
         // First buy should work properly even with zero initial shares
-        let mut market = MarketEngine::new(100);
-        let result = market.buy(Outcome::Yes, 100);
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        let result = market.buy(ALICE, Outcome::Yes, 100 * DECIMALS, None);
         assert!(result.is_ok());
         assert!(market.total_collateral > 0);
         
         // Price after buy should favor YES
-        let price_after = market.get_price();
-        assert!(price_after.yes > price_after.no);
+        let price_after = market.get_price().unwrap();
+        assert!(price_after.yes() > price_after.no());
     }
 
     #[test]
     fn test_sell_shares() {
         // Create market with some initial liquidity
-        let mut market = MarketEngine::new(100);
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
         
         // First, buy some shares so we have something to sell
         // Buy substantially more YES shares to ensure the price differential
-        market.buy(Outcome::Yes, 200).unwrap();
-        market.buy(Outcome::No, 50).unwrap();
+        market.buy(ALICE, Outcome::Yes, 200 * DECIMALS, None).unwrap();
+        market.buy(ALICE, Outcome::No, 50 * DECIMALS, None).unwrap();
         
         // Record state before selling
-        let initial_yes = market.q_yes;
-        let initial_no = market.q_no;
+        let initial_yes = market.quantity(Outcome::Yes);
+        let initial_no = market.quantity(Outcome::No);
         let initial_collateral = market.total_collateral;
-        let initial_price = market.get_price();
+        let initial_price = market.get_price().unwrap();
         
         // Verify initial price (YES should be higher since we bought more YES)
-        assert!(initial_price.yes > initial_price.no);
+        assert!(initial_price.yes() > initial_price.no());
         
         // Sell YES shares
-        let result = market.sell(Outcome::Yes, 100);
+        let result = market.sell(ALICE, Outcome::Yes, 100 * DECIMALS, None);
         assert!(result.is_ok());
-        let price = result.unwrap();
+        let price = result.unwrap().price_after;
         
         // Verify state changes
-        assert_eq!(market.q_yes, initial_yes - 100);
-        assert_eq!(market.q_no, initial_no); // NO shares shouldn't change
+        assert_eq!(market.quantity(Outcome::Yes), initial_yes - 100 * DECIMALS);
+        assert_eq!(market.quantity(Outcome::No), initial_no); // NO shares shouldn't change
         assert!(market.total_collateral < initial_collateral, 
                 "Collateral should decrease when selling shares. Was {} now {}", 
                 initial_collateral, market.total_collateral);
         
         // After selling YES shares, YES price should go down
-        assert!(price.yes < initial_price.yes,
+        assert!(price.yes() < initial_price.yes(),
                 "After selling YES shares, YES price should be lower: before={}, after={}",
-                initial_price.yes, price.yes);
+                initial_price.yes(), price.yes());
     }
     
     #[test]
     fn test_sell_insufficient_shares() {
         // Create market with some initial liquidity
-        let mut market = MarketEngine::new(100);
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
         
         // Buy fewer shares than we'll try to sell
-        market.buy(Outcome::Yes, 20).unwrap();
+        market.buy(ALICE, Outcome::Yes, 20 * DECIMALS, None).unwrap();
         
         // Try to sell more YES shares than we have
-        let result = market.sell(Outcome::Yes, 30);
+        let result = market.sell(ALICE, Outcome::Yes, 30 * DECIMALS, None);
         assert!(result.is_err());
         
         // Verify we get the correct error
         match result {
-            Err(TradeError::InsufficientCollateral) => {}, // This is expected
-            _ => panic!("Expected InsufficientCollateral error")
+            Err(TradeError::InsufficientPosition) => {}, // This is expected
+            _ => panic!("Expected InsufficientPosition error")
         }
         
         // State should remain unchanged
-        assert_eq!(market.q_yes, 20);
+        assert_eq!(market.quantity(Outcome::Yes), 20 * DECIMALS);
     }
     
     #[test]
     fn test_simulate_and_sell() {
         // Create market with some initial liquidity
-        let mut market = MarketEngine::new(100);
-        market.buy(Outcome::Yes, 100).unwrap();
-        market.buy(Outcome::No, 100).unwrap();
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::Yes, 100 * DECIMALS, None).unwrap();
+        market.buy(ALICE, Outcome::No, 100 * DECIMALS, None).unwrap();
         
         // Store the current state
         let initial_collateral = market.total_collateral;
         
         // First simulate selling using the simulate_sell function
-        let simulated_refund = market.simulate_sell(Outcome::Yes, 50).unwrap();
+        let simulated_refund = market.simulate_sell(Outcome::Yes, 50 * DECIMALS).unwrap();
         
         // Actual sell
         let before_sell_collateral = market.total_collateral;
-        market.sell(Outcome::Yes, 50).unwrap();
+        market.sell(ALICE, Outcome::Yes, 50 * DECIMALS, None).unwrap();
         let actual_refund = before_sell_collateral - market.total_collateral;
         
         // Verify refund calculation
@@ -218,7 +235,7 @@ mod tests {
                 simulated_refund, actual_refund);
         
         // Buy some YES shares to get back to a balanced state
-        market.buy(Outcome::Yes, 50).unwrap();
+        market.buy(ALICE, Outcome::Yes, 50 * DECIMALS, None).unwrap();
         
         // We should end up with approximately the initial collateral
         // (slight difference due to price impact)
@@ -232,24 +249,22 @@ mod tests {
     #[test]
     fn test_sell_all_shares() {
         // Test the edge case of selling all shares
-        let mut market = MarketEngine::new(100);
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
         
         // Buy equal amounts of YES and NO
-        market.buy(Outcome::Yes, 30).unwrap();
-        market.buy(Outcome::No, 30).unwrap();
-        
-        // Record state
-        let initial_collateral = market.total_collateral;
+        market.buy(ALICE, Outcome::Yes, 30 * DECIMALS, None).unwrap();
+        market.buy(ALICE, Outcome::No, 30 * DECIMALS, None).unwrap();
         
         // Sell all YES shares
-        market.sell(Outcome::Yes, 30).unwrap();
+        market.sell(ALICE, Outcome::Yes, 30 * DECIMALS, None).unwrap();
         
         // Sell all NO shares
-        market.sell(Outcome::No, 30).unwrap();
+        market.sell(ALICE, Outcome::No, 30 * DECIMALS, None).unwrap();
         
         // We should have no shares and almost no collateral (may be tiny rounding errors)
-        assert_eq!(market.q_yes, 0);
-        assert_eq!(market.q_no, 0);
+        assert_eq!(market.quantity(Outcome::Yes), 0);
+        assert_eq!(market.quantity(Outcome::No), 0);
         
         // Collateral should be very close to zero
         // (allowing for small rounding errors in float calculations)
@@ -258,11 +273,886 @@ mod tests {
                 market.total_collateral);
         
         // Check prices - should be back to 50/50
-        let price = market.get_price();
+        let price = market.get_price().unwrap();
         let half_decimals = DECIMALS / 2;
         let tolerance = DECIMALS / 100; // 1% tolerance
         
-        assert!((price.yes as i128 - half_decimals as i128).abs() < tolerance as i128);
-        assert!((price.no as i128 - half_decimals as i128).abs() < tolerance as i128);
+        assert!((price.yes() as i128 - half_decimals as i128).abs() < tolerance as i128);
+        assert!((price.no() as i128 - half_decimals as i128).abs() < tolerance as i128);
+    }
+
+    #[test]
+    fn test_trades_are_deterministic() {
+        // Replaying the same trades must give bit-identical state
+        let run = || {
+            let mut market = MarketEngine::new(DECIMALS);
+            fund(&mut market);
+            market.buy(ALICE, Outcome::Yes, 123 * DECIMALS / 10, None).unwrap();
+            market.buy(ALICE, Outcome::No, 7 * DECIMALS, None).unwrap();
+            market.sell(ALICE, Outcome::Yes, 3 * DECIMALS, None).unwrap();
+            (market.total_collateral, market.get_price().unwrap().yes())
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_categorical_market() {
+        let mut market = MarketEngine::with_outcomes(100 * DECIMALS, 4);
+        fund(&mut market);
+        assert_eq!(market.num_outcomes(), 4);
+
+        // Initial prices are uniform and sum to DECIMALS
+        let price = market.get_price().unwrap();
+        assert_eq!(price.outcomes.len(), 4);
+        assert_eq!(price.outcomes.iter().sum::<u128>(), DECIMALS);
+        assert_eq!(price.of(1usize), DECIMALS / 4);
+
+        // Buying one candidate raises its price above the others
+        let cost = market.simulate(2usize, 40 * DECIMALS).unwrap();
+        let price = market.buy(ALICE, 2usize, 40 * DECIMALS, None).unwrap().price_after;
+        assert_eq!(market.total_collateral, cost);
+        assert_eq!(market.quantity(2usize), 40 * DECIMALS);
+        assert!(price.of(2usize) > price.of(0usize));
+        assert_eq!(price.of(0usize), price.of(1usize));
+        assert_eq!(price.outcomes.iter().sum::<u128>(), DECIMALS);
+
+        // Selling everything returns the market to zero collateral
+        market.sell(ALICE, 2usize, 40 * DECIMALS, None).unwrap();
+        assert_eq!(market.total_collateral, 0);
+    }
+
+    #[test]
+    fn test_invalid_outcome() {
+        let mut market = MarketEngine::with_outcomes(100 * DECIMALS, 3);
+        fund(&mut market);
+
+        assert!(matches!(market.buy(ALICE, 3usize, DECIMALS, None), Err(TradeError::InvalidOutcome)));
+        assert!(matches!(market.sell(ALICE, 5usize, DECIMALS, None), Err(TradeError::InvalidOutcome)));
+        assert!(matches!(market.simulate_sell(3usize, DECIMALS), Err(TradeError::InvalidOutcome)));
+
+        // The getters report nothing held rather than panicking
+        market.buy(ALICE, 0usize, DECIMALS, None).unwrap();
+        assert_eq!(market.quantity(3usize), 0);
+        assert_eq!(market.position(ALICE, 3usize), 0);
+        assert_eq!(market.get_price().unwrap().of(3usize), 0);
+    }
+
+    #[test]
+    fn test_ls_lmsr_market() {
+        let alpha = DECIMALS / 20;
+        let mut market = MarketEngine::with_liquidity(LsLmsrLiquidity { alpha }, 3);
+        fund(&mut market);
+
+        let cost = market.simulate(0usize, 100 * DECIMALS).unwrap();
+        market.buy(ALICE, 0usize, 100 * DECIMALS, None).unwrap();
+        assert_eq!(market.total_collateral, cost);
+
+        let price = market.buy(ALICE, 1usize, 60 * DECIMALS, None).unwrap().price_after;
+        assert!(price.of(0usize) > price.of(1usize));
+        assert!(price.of(1usize) > price.of(2usize));
+
+        // LS-LMSR prices carry the market maker's vig
+        assert!(price.outcomes.iter().sum::<u128>() > DECIMALS);
+    }
+
+    #[test]
+    fn test_fixed_liquidity_market() {
+        // With a constant b the first buy is charged C(q) - C(0), not C(q)
+        let b = 100 * DECIMALS;
+        let mut market = MarketEngine::with_liquidity(FixedLiquidity { b }, 2);
+        fund(&mut market);
+
+        let cost = market.simulate(Outcome::Yes, 10 * DECIMALS).unwrap();
+        let expected = calc_cost(&[10 * DECIMALS, 0], b) - calc_cost(&[0, 0], b);
+        assert_eq!(cost, expected);
+
+        market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+        assert_eq!(market.total_collateral, expected);
+    }
+
+    #[test]
+    fn test_boxed_liquidity_market() {
+        // Liquidity functions can be chosen at runtime
+        let policies: Vec<Box<dyn LiquidityFunction>> = vec![
+            Box::new(SqrtLiquidity { alpha: DECIMALS }),
+            Box::new(FixedLiquidity { b: 50 * DECIMALS }),
+        ];
+
+        for liquidity in policies {
+            let mut market = MarketEngine::with_liquidity(liquidity, 2);
+            fund(&mut market);
+            let price = market.buy(ALICE, Outcome::No, 20 * DECIMALS, None).unwrap().price_after;
+            assert!(price.no() > price.yes());
+        }
+    }
+
+    #[test]
+    fn test_decaying_liquidity_market() {
+        let liquidity = DecayingLiquidity::new(FixedLiquidity { b: 100 * DECIMALS }, 1_000, 3_600).unwrap();
+        let mut market = MarketEngine::with_liquidity(liquidity, 2);
+        fund(&mut market);
+        market.set_time(1_000);
+        market.buy(ALICE, Outcome::Yes, 30 * DECIMALS, None).unwrap();
+        let early = market.get_price().unwrap();
+
+        // Less liquidity later on makes the same position more decisive
+        market.set_time(1_000 + 2 * 3_600);
+        let late = market.get_price().unwrap();
+        assert!(late.yes() > early.yes());
+    }
+
+    #[test]
+    fn test_account_balances() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        market.deposit("bob", 500 * DECIMALS).unwrap();
+
+        // Buying debits exactly the cost and records the position
+        let cost = market.simulate(Outcome::Yes, 40 * DECIMALS).unwrap();
+        market.buy("bob", Outcome::Yes, 40 * DECIMALS, None).unwrap();
+        assert_eq!(market.balance("bob"), 500 * DECIMALS - cost);
+        assert_eq!(market.position("bob", Outcome::Yes), 40 * DECIMALS);
+        assert_eq!(market.position("bob", Outcome::No), 0);
+
+        // Selling credits the refund
+        let refund = market.simulate_sell(Outcome::Yes, 15 * DECIMALS).unwrap();
+        market.sell("bob", Outcome::Yes, 15 * DECIMALS, None).unwrap();
+        assert_eq!(market.balance("bob"), 500 * DECIMALS - cost + refund);
+        assert_eq!(market.position("bob", Outcome::Yes), 25 * DECIMALS);
+
+        // Only free collateral can be withdrawn
+        let balance = market.balance("bob");
+        assert!(matches!(
+            market.withdraw("bob", balance + 1),
+            Err(TradeError::InsufficientCollateral)
+        ));
+        market.withdraw("bob", balance).unwrap();
+        assert_eq!(market.balance("bob"), 0);
+    }
+
+    #[test]
+    fn test_buy_insufficient_balance() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        market.deposit("bob", DECIMALS).unwrap();
+
+        let result = market.buy("bob", Outcome::Yes, 50 * DECIMALS, None);
+        assert!(matches!(result, Err(TradeError::InsufficientCollateral)));
+
+        // Nothing changed
+        assert_eq!(market.balance("bob"), DECIMALS);
+        assert_eq!(market.quantity(Outcome::Yes), 0);
+        assert_eq!(market.total_collateral, 0);
+
+        // Unknown traders have no collateral at all
+        let result = market.buy("mallory", Outcome::No, DECIMALS, None);
+        assert!(matches!(result, Err(TradeError::InsufficientCollateral)));
+    }
+
+    #[test]
+    fn test_cannot_sell_others_shares() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::Yes, 30 * DECIMALS, None).unwrap();
+
+        // The market has 30 YES shares, but bob owns none of them
+        market.deposit("bob", 100 * DECIMALS).unwrap();
+        let result = market.sell("bob", Outcome::Yes, 10 * DECIMALS, None);
+        assert!(matches!(result, Err(TradeError::InsufficientPosition)));
+
+        assert_eq!(market.quantity(Outcome::Yes), 30 * DECIMALS);
+        assert_eq!(market.position(ALICE, Outcome::Yes), 30 * DECIMALS);
+        assert_eq!(market.balance("bob"), 100 * DECIMALS);
+    }
+
+    #[test]
+    fn test_trading_stops_after_close() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+
+        market.close().unwrap();
+        assert_eq!(market.status, MarketStatus::Closed);
+        assert!(matches!(market.buy(ALICE, Outcome::Yes, DECIMALS, None), Err(TradeError::MarketClosed)));
+        assert!(matches!(market.sell(ALICE, Outcome::Yes, DECIMALS, None), Err(TradeError::MarketClosed)));
+        assert!(matches!(market.close(), Err(TradeError::MarketClosed)));
+    }
+
+    #[test]
+    fn test_close_time() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.close_time = Some(1_000);
+
+        // Cannot resolve while trading is still possible
+        market.set_time(999);
+        market.buy(ALICE, Outcome::No, 10 * DECIMALS, None).unwrap();
+        assert!(matches!(market.resolve(Outcome::No), Err(TradeError::MarketNotClosed)));
+
+        market.set_time(1_000);
+        assert!(!market.is_open());
+        assert!(matches!(market.buy(ALICE, Outcome::No, DECIMALS, None), Err(TradeError::MarketClosed)));
+
+        // Past the close time the market can be resolved without close()
+        market.resolve(Outcome::No).unwrap();
+        assert_eq!(market.status, MarketStatus::Resolved(Outcome::No.index()));
+    }
+
+    #[test]
+    fn test_resolve_pays_winners() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        market.deposit("bob", 1_000 * DECIMALS).unwrap();
+        market.deposit("carol", 1_000 * DECIMALS).unwrap();
+        market.buy("bob", Outcome::Yes, 30 * DECIMALS, None).unwrap();
+        market.buy("carol", Outcome::No, 20 * DECIMALS, None).unwrap();
+
+        let bob_before = market.balance("bob");
+        let carol_before = market.balance("carol");
+        let collateral_before = market.total_collateral;
+
+        market.close().unwrap();
+        let paid = market.resolve(Outcome::Yes).unwrap();
+
+        // Each winning share pays exactly one unit; losers get nothing
+        assert_eq!(paid, 30 * DECIMALS);
+        assert_eq!(market.balance("bob"), bob_before + 30 * DECIMALS);
+        assert_eq!(market.balance("carol"), carol_before);
+        assert_eq!(market.total_collateral, collateral_before - paid);
+
+        // Positions are redeemed
+        assert_eq!(market.position("bob", Outcome::Yes), 0);
+        assert_eq!(market.position("carol", Outcome::No), 0);
+
+        // The winner is priced at one unit, the loser at nothing
+        assert_eq!(market.get_price().unwrap().outcomes, vec![DECIMALS, 0]);
+
+        // A settled market stays settled
+        assert!(matches!(market.resolve(Outcome::No), Err(TradeError::MarketSettled)));
+        assert!(matches!(market.invalidate(), Err(TradeError::MarketSettled)));
+        assert!(matches!(market.buy("bob", Outcome::Yes, DECIMALS, None), Err(TradeError::MarketClosed)));
+    }
+
+    #[test]
+    fn test_failed_resolution_changes_nothing() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        market.close_time = Some(1_000);
+        market.deposit("bob", 1_000 * DECIMALS).unwrap();
+        market.buy("bob", Outcome::Yes, 10 * DECIMALS, None).unwrap();
+        market.deposit("bob", u128::MAX - market.balance("bob")).unwrap();
+        market.set_time(1_000);
+        let before = market.snapshot();
+
+        // Bob's payout would overflow his balance
+        assert!(matches!(market.resolve(Outcome::Yes), Err(TradeError::Overflow)));
+        assert!(matches!(market.resolve(5usize), Err(TradeError::InvalidOutcome)));
+        assert_eq!(market.snapshot(), before);
+        assert_eq!(market.status, MarketStatus::Open);
+
+        market.resolve(Outcome::No).unwrap();
+        assert_eq!(market.status, MarketStatus::Resolved(Outcome::No.index()));
+        assert_eq!(market.get_price().unwrap().outcomes, vec![0, DECIMALS]);
+    }
+
+    #[test]
+    fn test_invalid_market_refunds() {
+        let mut market = MarketEngine::with_outcomes(100 * DECIMALS, 4);
+        market.deposit("bob", 1_000 * DECIMALS).unwrap();
+        market.buy("bob", 0usize, 20 * DECIMALS, None).unwrap();
+        market.buy("bob", 3usize, 20 * DECIMALS, None).unwrap();
+        let before = market.balance("bob");
+
+        market.close().unwrap();
+        let paid = market.invalidate().unwrap();
+
+        // Every share is refunded 1 / n
+        assert_eq!(paid, 10 * DECIMALS);
+        assert_eq!(market.balance("bob"), before + 10 * DECIMALS);
+        assert_eq!(market.status, MarketStatus::Invalid);
+        assert_eq!(market.get_price().unwrap().outcomes, vec![DECIMALS / 4; 4]);
+    }
+
+    #[test]
+    fn test_simulate_with_collateral() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::Yes, 40 * DECIMALS, None).unwrap();
+
+        let budget = 25 * DECIMALS;
+        let shares = market.simulate_with_collateral(Outcome::No, budget);
+
+        // The shares fit the budget, and one more unit would not
+        assert!(market.simulate(Outcome::No, shares).unwrap() <= budget);
+        assert!(market.simulate(Outcome::No, shares + 1).unwrap() > budget);
+
+        assert_eq!(market.simulate_with_collateral(Outcome::Yes, 0), 0);
+    }
+
+    #[test]
+    fn test_buy_with_collateral() {
+        let mut market = MarketEngine::with_outcomes(100 * DECIMALS, 3);
+        market.deposit("bob", 100 * DECIMALS).unwrap();
+
+        let expected = market.simulate_with_collateral(2usize, 50 * DECIMALS);
+        let receipt = market.buy_with_collateral("bob", 2usize, 50 * DECIMALS).unwrap();
+        let (shares, price) = (receipt.shares, receipt.price_after);
+        assert_eq!(shares, expected);
+        assert_eq!(market.position("bob", 2usize), shares);
+        assert!(price.of(2usize) > price.of(0usize));
+
+        // Never spends more than the budget
+        let spent = 100 * DECIMALS - market.balance("bob");
+        assert!(spent <= 50 * DECIMALS);
+        assert!(50 * DECIMALS - spent < DECIMALS / 1_000_000);
+
+        // The budget is still checked against the trader's balance
+        let result = market.buy_with_collateral("bob", 0usize, 500 * DECIMALS);
+        assert!(matches!(result, Err(TradeError::InsufficientCollateral)));
+    }
+
+    #[test]
+    fn test_zero_share_trades_are_refused() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::Yes, DECIMALS, None).unwrap();
+        let before = market.snapshot();
+
+        let result = market.buy(ALICE, Outcome::Yes, 0, None);
+        assert!(matches!(result, Err(TradeError::ZeroAmount)));
+        let result = market.sell(ALICE, Outcome::Yes, 0, None);
+        assert!(matches!(result, Err(TradeError::ZeroAmount)));
+        let result = market.quote(Side::Buy, Outcome::Yes, 0);
+        assert!(matches!(result, Err(TradeError::ZeroAmount)));
+
+        // A budget too small for one share is not enough collateral
+        assert_eq!(market.simulate_with_collateral(Outcome::Yes, 0), 0);
+        let result = market.buy_with_collateral(ALICE, Outcome::Yes, 0);
+        assert!(matches!(result, Err(TradeError::InsufficientCollateral)));
+        assert_eq!(market.snapshot(), before);
+    }
+
+    #[test]
+    fn test_buy_slippage_limit() {
+        let mut market = MarketEngine::with_liquidity(FixedLiquidity { b: 100 * DECIMALS }, 2);
+        fund(&mut market);
+
+        // Quote, then someone else moves the market first
+        let quote = market.simulate(Outcome::Yes, 20 * DECIMALS).unwrap();
+        market.buy(ALICE, Outcome::Yes, 50 * DECIMALS, None).unwrap();
+        let actual = market.simulate(Outcome::Yes, 20 * DECIMALS).unwrap();
+
+        market.deposit("bob", 1_000 * DECIMALS).unwrap();
+        let result = market.buy("bob", Outcome::Yes, 20 * DECIMALS, Some(quote));
+        match result {
+            Err(TradeError::SlippageExceeded { quoted, actual: paid }) => {
+                assert_eq!(quoted, quote);
+                assert_eq!(paid, actual);
+            }
+            other => panic!("Expected SlippageExceeded, got {:?}", other),
+        }
+        assert_eq!(market.balance("bob"), 1_000 * DECIMALS);
+
+        // A limit at or above the actual cost goes through
+        market.buy("bob", Outcome::Yes, 20 * DECIMALS, Some(actual)).unwrap();
+        assert_eq!(market.position("bob", Outcome::Yes), 20 * DECIMALS);
+    }
+
+    #[test]
+    fn test_sell_slippage_limit() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::No, 60 * DECIMALS, None).unwrap();
+
+        let refund = market.simulate_sell(Outcome::No, 10 * DECIMALS).unwrap();
+        let result = market.sell(ALICE, Outcome::No, 10 * DECIMALS, Some(refund + 1));
+        assert!(matches!(result, Err(TradeError::SlippageExceeded { .. })));
+        assert_eq!(market.position(ALICE, Outcome::No), 60 * DECIMALS);
+
+        market.sell(ALICE, Outcome::No, 10 * DECIMALS, Some(refund)).unwrap();
+        assert_eq!(market.position(ALICE, Outcome::No), 50 * DECIMALS);
+    }
+
+    #[test]
+    fn test_trade_limits() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.limits = TradeLimits {
+            max_trade: Some(10 * DECIMALS),
+            max_position: Some(25 * DECIMALS),
+        };
+
+        let result = market.buy(ALICE, Outcome::Yes, 10 * DECIMALS + 1, None);
+        assert!(matches!(result, Err(TradeError::TradeTooLarge { max }) if max == 10 * DECIMALS));
+        let result = market.quote(Side::Buy, Outcome::Yes, 11 * DECIMALS);
+        assert!(matches!(result, Err(TradeError::TradeTooLarge { .. })));
+
+        market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+        market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+        let result = market.buy(ALICE, Outcome::Yes, 6 * DECIMALS, None);
+        assert!(matches!(result, Err(TradeError::PositionLimitExceeded { max }) if max == 25 * DECIMALS));
+        assert_eq!(market.position(ALICE, Outcome::Yes), 20 * DECIMALS);
+
+        // Other outcomes have their own room
+        market.buy(ALICE, Outcome::No, 5 * DECIMALS, None).unwrap();
+
+        // Sells are bounded by size only
+        let result = market.sell(ALICE, Outcome::Yes, 20 * DECIMALS, None);
+        assert!(matches!(result, Err(TradeError::TradeTooLarge { .. })));
+        market.sell(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+    }
+
+    #[test]
+    fn test_subsidy_covers_worst_case() {
+        let b = 100 * DECIMALS;
+        let mut market = MarketEngine::with_liquidity(FixedLiquidity { b }, 2);
+        market.deposit(ALICE, 1_000_000 * DECIMALS).unwrap();
+        assert_eq!(market.max_loss(), calc_cost(&[0, 0], b));
+
+        // Without a subsidy a constant-b market cannot pay out its first buyer
+        let result = market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None);
+        assert!(matches!(result, Err(TradeError::Insolvent { .. })));
+        let result = market.quote(Side::Buy, Outcome::Yes, 10 * DECIMALS);
+        assert!(matches!(result, Err(TradeError::Insolvent { .. })));
+        assert_eq!(market.sequence, 0);
+
+        // Half the bound covers small trades but not lopsided ones
+        market.subsidy = market.max_loss() / 2;
+        market.buy(ALICE, Outcome::Yes, 20 * DECIMALS, None).unwrap();
+        let result = market.buy(ALICE, Outcome::Yes, 200 * DECIMALS, None);
+        assert!(matches!(result, Err(TradeError::Insolvent { .. })));
+
+        // The full bound covers anything
+        market.subsidy = market.max_loss();
+        market.buy(ALICE, Outcome::Yes, 1_000 * DECIMALS, None).unwrap();
+        let solvency = market.solvency();
+        assert_eq!(solvency.max_payout, 1_020 * DECIMALS);
+        assert_eq!(solvency.total_collateral, market.total_collateral);
+        assert_eq!(solvency.current_loss, solvency.max_payout - solvency.total_collateral);
+        assert!(solvency.current_loss <= solvency.max_loss);
+
+        // Settling takes the loss out of the subsidy
+        market.close().unwrap();
+        assert_eq!(market.resolve(Outcome::Yes).unwrap(), 1_020 * DECIMALS);
+        assert_eq!(market.balance(ALICE), 1_000_000 * DECIMALS - solvency.total_collateral + 1_020 * DECIMALS);
+    }
+
+    #[test]
+    fn test_solvency_after_settlement() {
+        let b = 100 * DECIMALS;
+        let mut market = MarketEngine::with_liquidity(FixedLiquidity { b }, 2);
+        market.subsidy = market.max_loss();
+        market.deposit(ALICE, 10_000 * DECIMALS).unwrap();
+        market.buy(ALICE, Outcome::Yes, 1_000 * DECIMALS, None).unwrap();
+        let before = market.solvency();
+        assert!(before.current_loss > 0);
+
+        // Once resolved the report is what was paid and lost, not what is still held
+        market.close().unwrap();
+        let paid = market.resolve(Outcome::Yes).unwrap();
+        let after = market.solvency();
+        assert_eq!(after.max_payout, paid);
+        assert_eq!(after.current_loss, paid - before.total_collateral);
+        assert_eq!(after.total_collateral, 0);
+        assert_eq!(after.subsidy, before.subsidy - after.current_loss);
+        assert_eq!(after.max_loss, 0);
+        assert_eq!(market.quantities, vec![0, 0]);
+
+        // The settlement survives a snapshot
+        let restored = MarketEngine::from_snapshot(market.snapshot()).unwrap();
+        assert_eq!(restored.solvency(), after);
+
+        // Refunding at the average price loses nothing
+        let mut market = MarketEngine::new(DECIMALS);
+        market.deposit(ALICE, 100 * DECIMALS).unwrap();
+        market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+        market.close().unwrap();
+        let paid = market.invalidate().unwrap();
+        let solvency = market.solvency();
+        assert_eq!(solvency.max_payout, paid);
+        assert_eq!(solvency.current_loss, 0);
+    }
+
+    #[test]
+    fn test_trading_fees() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.fees = FeeSchedule::Flat { bps: 100 };
+
+        let quote = market.quote(Side::Buy, Outcome::Yes, 20 * DECIMALS).unwrap();
+        assert_eq!(quote.fee, quote.collateral / 100);
+
+        // Buys pay the fee on top; it stays out of the collateral backing shares
+        let receipt = market.buy(ALICE, Outcome::Yes, 20 * DECIMALS, None).unwrap();
+        assert_eq!((receipt.collateral, receipt.fee), (quote.collateral, quote.fee));
+        assert_eq!(market.balance(ALICE), 1_000_000 * DECIMALS - receipt.collateral - receipt.fee);
+        assert_eq!(market.total_collateral, receipt.collateral);
+        assert_eq!(market.fees_collected, receipt.fee);
+
+        // Sells pay it out of the refund, and the slippage limit is on what is left
+        let before = market.balance(ALICE);
+        let refund = market.simulate_sell(Outcome::Yes, 10 * DECIMALS).unwrap();
+        let result = market.sell(ALICE, Outcome::Yes, 10 * DECIMALS, Some(refund));
+        assert!(matches!(result, Err(TradeError::SlippageExceeded { .. })));
+        let sold = market.sell(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+        assert_eq!(sold.fee, refund / 100);
+        assert_eq!(market.balance(ALICE), before + refund - sold.fee);
+        assert_eq!(market.fees_collected, receipt.fee + sold.fee);
+        assert_eq!(market.account(ALICE).unwrap().volume, receipt.collateral + refund);
+
+        let collected = market.fees_collected;
+        assert!(matches!(market.withdraw_fees(collected + 1), Err(TradeError::InsufficientCollateral)));
+        market.withdraw_fees(collected).unwrap();
+        assert_eq!(market.fees_collected, 0);
+    }
+
+    #[test]
+    fn test_fees_count_against_budgets() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        market.deposit("bob", 100 * DECIMALS).unwrap();
+        market.fees = FeeSchedule::Flat { bps: 500 };
+
+        let budget = 50 * DECIMALS;
+        let shares = market.simulate_with_collateral(Outcome::Yes, budget);
+        let receipt = market.buy_with_collateral("bob", Outcome::Yes, budget).unwrap();
+        assert_eq!(receipt.shares, shares);
+        assert!(receipt.collateral + receipt.fee <= budget);
+        assert!(budget - receipt.collateral - receipt.fee < DECIMALS / 1_000_000);
+
+        // Volume earns cheaper tiers
+        market.fees = FeeSchedule::Tiered {
+            tiers: vec![
+                FeeTier { min_volume: 0, bps: 500 },
+                FeeTier { min_volume: 10 * DECIMALS, bps: 0 },
+            ],
+        };
+        let receipt = market.buy("bob", Outcome::No, DECIMALS, None).unwrap();
+        assert_eq!(receipt.fee, 0);
+        let quote = market.quote(Side::Buy, Outcome::No, DECIMALS).unwrap();
+        assert!(quote.fee > 0);
+    }
+
+    #[test]
+    fn test_collateral_buys_stop_at_limits() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.limits.max_trade = Some(10 * DECIMALS);
+        assert_eq!(market.simulate_with_collateral(Outcome::Yes, 1_000 * DECIMALS), 10 * DECIMALS);
+
+        // A budget too small to reach the limit is unaffected
+        let shares = market.simulate_with_collateral(Outcome::Yes, DECIMALS);
+        assert!(shares < 10 * DECIMALS);
+        assert!(market.simulate(Outcome::Yes, shares + 1).unwrap() > DECIMALS);
+
+        market.limits.max_position = Some(15 * DECIMALS);
+        let receipt = market.buy_with_collateral(ALICE, Outcome::Yes, 1_000 * DECIMALS).unwrap();
+        assert_eq!(receipt.shares, 10 * DECIMALS);
+        let receipt = market.buy_with_collateral(ALICE, Outcome::Yes, 1_000 * DECIMALS).unwrap();
+        assert_eq!(receipt.shares, 5 * DECIMALS);
+        assert_eq!(market.position(ALICE, Outcome::Yes), 15 * DECIMALS);
+        let result = market.buy_with_collateral(ALICE, Outcome::Yes, 1_000 * DECIMALS);
+        assert!(matches!(result, Err(TradeError::PositionLimitExceeded { .. })));
+    }
+
+    #[test]
+    fn test_trade_receipts() {
+        let mut market = MarketEngine::with_liquidity(FixedLiquidity { b: 100 * DECIMALS }, 2);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::No, 10 * DECIMALS, None).unwrap();
+
+        let price_before = market.get_price().unwrap();
+        let b_before = market.current_b().unwrap();
+        let cost = market.simulate(Outcome::Yes, 25 * DECIMALS).unwrap();
+
+        let receipt = market.buy(ALICE, Outcome::Yes, 25 * DECIMALS, None).unwrap();
+        assert_eq!(receipt.sequence, 2);
+        assert_eq!(receipt.trader, ALICE);
+        assert_eq!(receipt.side, Side::Buy);
+        assert_eq!(receipt.outcome, Outcome::Yes.index());
+        assert_eq!(receipt.shares, 25 * DECIMALS);
+        assert_eq!(receipt.collateral, cost);
+        assert_eq!(receipt.price_before, price_before);
+        assert_eq!(receipt.price_after, market.get_price().unwrap());
+        assert_eq!(receipt.b_before, b_before);
+        assert_eq!(receipt.b_after, market.current_b().unwrap());
+        assert_eq!(receipt.average_price, cost * DECIMALS / (25 * DECIMALS));
+
+        // The fill lies between the marginal prices before and after the trade
+        assert!(receipt.average_price > receipt.price_before.yes());
+        assert!(receipt.average_price < receipt.price_after.yes());
+
+        let refund = market.simulate_sell(Outcome::Yes, 5 * DECIMALS).unwrap();
+        let receipt = market.sell(ALICE, Outcome::Yes, 5 * DECIMALS, None).unwrap();
+        assert_eq!(receipt.sequence, 3);
+        assert_eq!(receipt.side, Side::Sell);
+        assert_eq!(receipt.collateral, refund);
+        assert_eq!(market.sequence, 3);
+
+        // Rejected trades do not consume a sequence number
+        assert!(market.sell(ALICE, Outcome::Yes, 500 * DECIMALS, None).is_err());
+        assert_eq!(market.sequence, 3);
+    }
+
+    #[test]
+    fn test_overflowing_trades_are_rejected() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::Yes, 10 * DECIMALS, None).unwrap();
+
+        assert!(matches!(market.simulate(Outcome::Yes, u128::MAX), Err(TradeError::Overflow)));
+        assert!(matches!(
+            market.buy(ALICE, Outcome::No, u128::MAX, None),
+            Err(TradeError::Overflow)
+        ));
+        assert!(matches!(
+            market.buy(ALICE, Outcome::No, u128::MAX / 2, None),
+            Err(TradeError::InsufficientCollateral)
+        ));
+        assert!(matches!(market.simulate(5usize, DECIMALS), Err(TradeError::InvalidOutcome)));
+
+        // A budget too large to spend buys only what can still be priced
+        let shares = market.simulate_with_collateral(Outcome::No, u128::MAX);
+        assert!(market.simulate(Outcome::No, shares).is_ok());
+
+        // The failed trades left the market untouched
+        assert_eq!(market.quantity(Outcome::Yes), 10 * DECIMALS);
+        assert_eq!(market.quantity(Outcome::No), 0);
+        assert_eq!(market.sequence, 1);
+    }
+
+    #[test]
+    fn test_deposit_overflow() {
+        let mut market = MarketEngine::new(100 * DECIMALS);
+        market.deposit(ALICE, u128::MAX).unwrap();
+        assert!(matches!(market.deposit(ALICE, 1), Err(TradeError::Overflow)));
+        assert_eq!(market.balance(ALICE), u128::MAX);
+    }
+
+    #[test]
+    fn test_price_with_no_liquidity() {
+        // b = alpha * sqrt(1 wei) rounds to 0, so the leader takes all the weight
+        let mut market = MarketEngine::with_outcomes(1_000, 2);
+        fund(&mut market);
+        let receipt = market.buy(ALICE, Outcome::Yes, 1, None).unwrap();
+        assert_eq!(receipt.price_after.outcomes, vec![DECIMALS, 0]);
+        assert_eq!(market.get_price().unwrap().outcomes, vec![DECIMALS, 0]);
+
+        // Ties share it
+        market.buy(ALICE, Outcome::No, 1, None).unwrap();
+        assert_eq!(market.get_price().unwrap().outcomes, vec![DECIMALS / 2, DECIMALS / 2]);
+
+        // Decayed liquidity tends to the same limit
+        let liquidity = DecayingLiquidity::new(FixedLiquidity { b: 100 * DECIMALS }, 0, 1).unwrap();
+        let mut market = MarketEngine::with_liquidity(liquidity, 3);
+        fund(&mut market);
+        market.buy(ALICE, 1usize, 10 * DECIMALS, None).unwrap();
+        market.set_time(1_000);
+        assert_eq!(market.current_b().unwrap(), 0);
+        assert_eq!(market.get_price().unwrap().outcomes, vec![0, DECIMALS, 0]);
+    }
+
+    #[test]
+    fn test_liquidity_overflow_is_an_error() {
+        // b = alpha * sqrt(sum(q)) does not fit, so nothing can be priced
+        let mut market = MarketEngine::new(u128::MAX);
+        fund(&mut market);
+        let result = market.buy(ALICE, Outcome::Yes, 4 * DECIMALS, None);
+        assert!(matches!(result, Err(TradeError::Overflow)));
+        assert_eq!(market.sequence, 0);
+        assert_eq!(market.quantity(Outcome::Yes), 0);
+        assert_eq!(market.balance(ALICE), 1_000_000 * DECIMALS);
+
+        // Likewise quoting a huge trade under LS-LMSR
+        let market = MarketEngine::with_liquidity(LsLmsrLiquidity { alpha: 1_000_000_000_000 * DECIMALS }, 2);
+        let result = market.quote(Side::Buy, Outcome::Yes, 1_000_000_000_000_000_000 * DECIMALS);
+        assert!(matches!(result, Err(TradeError::Overflow)), "{:?}", result);
+    }
+
+    // Runs `trades` against a well-funded market and checks every quote
+    // and price along the way stays well formed.
+    fn check_extreme_trades<L: LiquidityFunction>(
+        mut market: MarketEngine<L>,
+        trades: &[(usize, u128)],
+        price_cap: u128,
+    ) -> Result<(), TestCaseError> {
+        market.deposit(ALICE, u128::MAX / 2).unwrap();
+        market.subsidy = market.max_loss();
+
+        for &(outcome, amount) in trades {
+            let outcome = outcome % market.num_outcomes();
+            let cost = market.simulate(outcome, amount).unwrap();
+            let receipt = market.buy(ALICE, outcome, amount, None).unwrap();
+            prop_assert_eq!(receipt.collateral, cost);
+
+            let prices = market.get_price().unwrap().outcomes;
+            prop_assert!(prices.iter().all(|&p| p <= price_cap), "{:?}", prices);
+        }
+
+        // Selling everything back refunds no more than was paid in
+        for outcome in 0..market.num_outcomes() {
+            let held = market.position(ALICE, outcome);
+            if held > 0 {
+                market.sell(ALICE, outcome, held, None).unwrap();
+            }
+        }
+        prop_assert!(market.balance(ALICE) <= u128::MAX / 2);
+        Ok(())
+    }
+
+    // Buys `yes` and `no` shares, then quotes tiny trades either way. The
+    // rounded cost is not exactly monotone, but none of them may fail.
+    fn check_tiny_trades<L: LiquidityFunction>(
+        mut market: MarketEngine<L>,
+        yes: u128,
+        no: u128,
+        tiny: u128,
+    ) -> Result<(), TestCaseError> {
+        market.deposit(ALICE, 1_000_000 * DECIMALS).unwrap();
+        market.subsidy = market.max_loss();
+        market.buy(ALICE, Outcome::Yes, yes, None).unwrap();
+        market.buy(ALICE, Outcome::No, no, None).unwrap();
+
+        for outcome in [Outcome::Yes, Outcome::No] {
+            let cost = market.simulate(outcome, tiny).unwrap();
+            prop_assert!(cost >= 1);
+            market.simulate_sell(outcome, tiny).unwrap();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_tiny_sell_after_rounding() {
+        let mut market = MarketEngine::new(DECIMALS);
+        fund(&mut market);
+        market.buy(ALICE, Outcome::Yes, 14_423_424_517_477_000_000, None).unwrap();
+        market.buy(ALICE, Outcome::No, 14_173_963_207_395_000_000, None).unwrap();
+        for amount in 1..=5 {
+            market.simulate_sell(Outcome::Yes, amount).unwrap();
+            assert!(market.simulate(Outcome::Yes, amount).unwrap() >= 1);
+        }
+    }
+
+    fn trades() -> impl Strategy<Value = Vec<(usize, u128)>> {
+        prop::collection::vec((0..4usize, 1..=1_000_000_000_000 * DECIMALS), 1..8)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_sqrt_market_survives_imbalance(trades in trades(), n in 2..=4usize) {
+            let market = MarketEngine::with_outcomes(DECIMALS, n);
+            check_extreme_trades(market, &trades, DECIMALS)?;
+        }
+
+        #[test]
+        fn prop_fixed_market_survives_imbalance(trades in trades(), b in DECIMALS..=1_000 * DECIMALS) {
+            let market = MarketEngine::with_liquidity(FixedLiquidity { b }, 3);
+            check_extreme_trades(market, &trades, DECIMALS)?;
+        }
+
+        #[test]
+        fn prop_ls_market_survives_imbalance(trades in trades()) {
+            let market = MarketEngine::with_liquidity(LsLmsrLiquidity { alpha: DECIMALS / 20 }, 3);
+            check_extreme_trades(market, &trades, DECIMALS + DECIMALS / 10)?;
+        }
+
+        #[test]
+        fn prop_sqrt_market_prices_tiny_trades(
+            yes in DECIMALS..=100 * DECIMALS,
+            no in DECIMALS..=100 * DECIMALS,
+            tiny in 1..=1_000_000u128,
+        ) {
+            check_tiny_trades(MarketEngine::new(DECIMALS), yes, no, tiny)?;
+        }
+
+        #[test]
+        fn prop_fixed_market_prices_tiny_trades(
+            yes in DECIMALS..=100 * DECIMALS,
+            no in DECIMALS..=100 * DECIMALS,
+            tiny in 1..=1_000_000u128,
+        ) {
+            let market = MarketEngine::with_liquidity(FixedLiquidity { b: 100 * DECIMALS }, 2);
+            check_tiny_trades(market, yes, no, tiny)?;
+        }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut market = MarketEngine::with_outcomes(10 * DECIMALS, 3);
+        fund(&mut market);
+        market.fees = FeeSchedule::Tiered {
+            tiers: vec![
+                FeeTier { min_volume: 0, bps: 50 },
+                FeeTier { min_volume: 100 * DECIMALS, bps: 20 },
+            ],
+        };
+        market.deposit("bob", 500 * DECIMALS).unwrap();
+        market.buy(ALICE, 0usize, 40 * DECIMALS, None).unwrap();
+        market.buy("bob", 2usize, 15 * DECIMALS, None).unwrap();
+        market.sell(ALICE, 0usize, 5 * DECIMALS, None).unwrap();
+        market.close_time = Some(1_000);
+        market.set_time(500);
+
+        let json = serde_json::to_string(&market.snapshot()).unwrap();
+        let state: MarketState<SqrtLiquidity> = serde_json::from_str(&json).unwrap();
+        assert_eq!(state, market.snapshot());
+        assert_eq!(state.version, SNAPSHOT_VERSION);
+
+        let mut restored = MarketEngine::from_snapshot(state).unwrap();
+        assert_eq!(restored.snapshot(), market.snapshot());
+        assert_eq!(restored.get_price().unwrap(), market.get_price().unwrap());
+
+        // The restored market carries on exactly where the original left off
+        let expected = market.buy("bob", 1usize, 7 * DECIMALS, None).unwrap();
+        let actual = restored.buy("bob", 1usize, 7 * DECIMALS, None).unwrap();
+        assert_eq!(actual, expected);
+        assert_eq!(restored.sequence, 4);
+    }
+
+    #[test]
+    fn test_snapshot_rejects_bad_state() {
+        let mut market = MarketEngine::new(DECIMALS);
+        fund(&mut market);
+
+        let mut state = market.snapshot();
+        state.version = SNAPSHOT_VERSION + 1;
+        assert!(matches!(
+            MarketEngine::from_snapshot(state),
+            Err(TradeError::UnsupportedSnapshot { version }) if version == SNAPSHOT_VERSION + 1
+        ));
+
+        let mut state = market.snapshot();
+        state.quantities.push(0);
+        assert!(matches!(MarketEngine::from_snapshot(state), Err(TradeError::InvalidSnapshot)));
+
+        let mut state = market.snapshot();
+        state.status = MarketStatus::Resolved(2);
+        assert!(matches!(MarketEngine::from_snapshot(state), Err(TradeError::InvalidSnapshot)));
+    }
+
+    #[test]
+    fn test_quotes_match_trades() {
+        let mut market = MarketEngine::with_outcomes(10 * DECIMALS, 3);
+        fund(&mut market);
+
+        let quote = market.quote(Side::Buy, 1usize, 30 * DECIMALS).unwrap();
+        let receipt = market.buy(ALICE, 1usize, 30 * DECIMALS, None).unwrap();
+        assert_eq!(quote.collateral, receipt.collateral);
+        assert_eq!(quote.average_price, receipt.average_price);
+        assert_eq!(quote.price_before, receipt.price_before);
+        assert_eq!(quote.price_after, receipt.price_after);
+
+        let quote = market.quote(Side::Sell, 1usize, 12 * DECIMALS).unwrap();
+        assert_eq!(quote.side, Side::Sell);
+        assert_eq!(quote.collateral, market.simulate_sell(1usize, 12 * DECIMALS).unwrap());
+        assert!(quote.price_after.of(1usize) < quote.price_before.of(1usize));
+
+        let receipt = market.sell(ALICE, 1usize, 12 * DECIMALS, None).unwrap();
+        assert_eq!(quote.collateral, receipt.collateral);
+        assert_eq!(quote.price_after, receipt.price_after);
+
+        // Quoting never changes the market
+        assert_eq!(market.sequence, 2);
+        assert!(matches!(
+            market.quote(Side::Sell, 0usize, DECIMALS),
+            Err(TradeError::InsufficientPosition)
+        ));
+        assert!(matches!(market.quote(Side::Buy, 3usize, DECIMALS), Err(TradeError::InvalidOutcome)));
     }
 }
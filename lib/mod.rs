@@ -1,8 +1,23 @@
+pub mod fees;
+pub mod fixed;
+pub mod history;
+pub mod journal;
+pub mod liquidity;
 pub mod lslmsr;
 pub mod market;
 pub mod types;
 
+#[cfg(test)]
+mod fees_test;
+#[cfg(test)]
+mod fixed_test;
+#[cfg(test)]
+mod history_test;
+#[cfg(test)]
+mod journal_test;
+#[cfg(test)]
+mod liquidity_test;
 #[cfg(test)]
 mod lslmsr_test;
 #[cfg(test)]
-mod market_test;
\ No newline at end of file
+mod market_test;
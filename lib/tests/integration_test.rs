@@ -1,5 +1,4 @@
 use lslmsr::{
-    lslmsr::{calc_b, calc_cost, calc_price},
     market::MarketEngine,
     types::{Outcome, DECIMALS},
 };
@@ -9,41 +8,42 @@ fn test_market_lifecycle() {
     // Initialize market with alpha parameter
     let alpha = 100 * DECIMALS;
     let mut market = MarketEngine::new(alpha);
+    market.deposit("alice", 1_000_000 * DECIMALS).unwrap();
     
     // Verify initial state
-    assert_eq!(market.q_yes, 0);
-    assert_eq!(market.q_no, 0);
+    assert_eq!(market.quantity(Outcome::Yes), 0);
+    assert_eq!(market.quantity(Outcome::No), 0);
     
     // Get initial price (should be around 50/50)
-    let initial_price = market.get_price();
+    let initial_price = market.get_price().unwrap();
     let expected_initial = (0.5 * DECIMALS as f64) as u128;
     let tolerance = DECIMALS / 100; // 1% tolerance
     
-    assert!((initial_price.yes as i128 - expected_initial as i128).abs() < tolerance as i128);
-    assert!((initial_price.no as i128 - expected_initial as i128).abs() < tolerance as i128);
+    assert!((initial_price.yes() as i128 - expected_initial as i128).abs() < tolerance as i128);
+    assert!((initial_price.no() as i128 - expected_initial as i128).abs() < tolerance as i128);
     
     // Simulate a buy before making the actual purchase
-    let simulated_cost = market.simulate(Outcome::Yes, 500 * DECIMALS);
+    let simulated_cost = market.simulate(Outcome::Yes, 500 * DECIMALS).unwrap();
     
     // Make the actual purchase
-    let price_after_buy = market.buy(Outcome::Yes, 500 * DECIMALS).unwrap();
+    let price_after_buy = market.buy("alice", Outcome::Yes, 500 * DECIMALS, None).unwrap().price_after;
     
     // Verify the simulation was accurate
     assert_eq!(simulated_cost, market.total_collateral);
     
     // Verify the YES price increased and NO price decreased
-    assert!(price_after_buy.yes > initial_price.yes);
-    assert!(price_after_buy.no < initial_price.no);
+    assert!(price_after_buy.yes() > initial_price.yes());
+    assert!(price_after_buy.no() < initial_price.no());
     
     // Buy more of the opposite outcome to balance the market
-    let _ = market.buy(Outcome::No, 500 * DECIMALS).unwrap();
+    let _ = market.buy("alice", Outcome::No, 500 * DECIMALS, None).unwrap();
     
     // Get final prices
-    let final_price = market.get_price();
+    let final_price = market.get_price().unwrap();
     
     // Prices should be closer to balanced again
-    let final_diff = (final_price.yes as i128 - final_price.no as i128).abs();
-    let after_buy_diff = (price_after_buy.yes as i128 - price_after_buy.no as i128).abs();
+    let final_diff = (final_price.yes() as i128 - final_price.no() as i128).abs();
+    let after_buy_diff = (price_after_buy.yes() as i128 - price_after_buy.no() as i128).abs();
     
     assert!(final_diff < after_buy_diff);
 }
@@ -52,6 +52,7 @@ fn test_market_lifecycle() {
 fn test_large_trades() {
     let alpha = 1000 * DECIMALS;
     let mut market = MarketEngine::new(alpha);
+    market.deposit("alice", 1_000_000 * DECIMALS).unwrap();
     
     // Make a series of increasingly large trades
     let trades = [
@@ -63,13 +64,13 @@ fn test_large_trades() {
     
     for (outcome, amount) in trades.iter() {
         // Simulate first
-        let simulated_cost = market.simulate(*outcome, *amount);
+        let simulated_cost = market.simulate(*outcome, *amount).unwrap();
         
         // Get price before
-        let price_before = market.get_price();
+        let price_before = market.get_price().unwrap();
         
         // Execute trade
-        let price_after = market.buy(*outcome, *amount).unwrap();
+        let price_after = market.buy("alice", *outcome, *amount, None).unwrap().price_after;
         
         // Verify simulation accuracy
         let old_collateral = market.total_collateral - simulated_cost;
@@ -78,12 +79,12 @@ fn test_large_trades() {
         // Price should move in expected direction
         match outcome {
             Outcome::Yes => {
-                assert!(price_after.yes > price_before.yes);
-                assert!(price_after.no < price_before.no);
+                assert!(price_after.yes() > price_before.yes());
+                assert!(price_after.no() < price_before.no());
             },
             Outcome::No => {
-                assert!(price_after.no > price_before.no);
-                assert!(price_after.yes < price_before.yes);
+                assert!(price_after.no() > price_before.no());
+                assert!(price_after.yes() < price_before.yes());
             },
         }
     }
@@ -1,27 +1,205 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fees::FeeSchedule;
+
 // constants
 pub const DECIMALS: u128 = 1_000_000_000_000_000_000; // 1e18
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Named outcomes of a binary market. Categorical markets address
+/// outcomes by index; `Yes` is index 0 and `No` is index 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Outcome {
     Yes,
     No,
 }
 
-#[derive(Debug)]
+impl Outcome {
+    pub fn index(self) -> usize {
+        match self {
+            Outcome::Yes => 0,
+            Outcome::No => 1,
+        }
+    }
+}
+
+impl From<Outcome> for usize {
+    fn from(outcome: Outcome) -> usize {
+        outcome.index()
+    }
+}
+
+/// Marginal price of every outcome, indexed like the market's quantities.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Price {
-    pub yes: u128,
-    pub no: u128,
+    pub outcomes: Vec<u128>,
+}
+
+impl Price {
+    /// The price of `outcome`; 0 for an outcome the market does not have.
+    pub fn of(&self, outcome: impl Into<usize>) -> u128 {
+        self.outcomes.get(outcome.into()).copied().unwrap_or(0)
+    }
+
+    pub fn yes(&self) -> u128 {
+        self.of(Outcome::Yes)
+    }
+
+    pub fn no(&self) -> u128 {
+        self.of(Outcome::No)
+    }
+}
+
+/// Market lifecycle: `Open` -> `Closed` -> `Resolved(outcome)` or `Invalid`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MarketStatus {
+    #[default]
+    Open,
+    Closed,
+    Resolved(usize),
+    Invalid,
+}
+
+pub type TraderId = String;
+
+/// A trader's free collateral and share position in every outcome.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    pub balance: u128,
+    pub positions: Vec<u128>,
+    /// Collateral traded so far, fees aside, for volume-tiered fees.
+    pub volume: u128,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// Everything that happened in one executed trade.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeReceipt {
+    /// Position of this trade in the market's history, starting at 1.
+    pub sequence: u64,
+    pub trader: TraderId,
+    pub side: Side,
+    pub outcome: usize,
+    pub shares: u128,
+    /// Collateral paid for a buy, or refunded for a sell, before fees.
+    pub collateral: u128,
+    /// Fee charged on top of a buy's collateral, or out of a sell's.
+    pub fee: u128,
+    pub price_before: Price,
+    pub price_after: Price,
+    /// `collateral / shares`, or 0 for an empty trade.
+    pub average_price: u128,
+    pub b_before: u128,
+    pub b_after: u128,
+}
+
+/// What a trade would do at the current market state, without executing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quote {
+    pub side: Side,
+    pub outcome: usize,
+    pub shares: u128,
+    /// Collateral a buy would cost, or a sell would refund, before fees.
+    pub collateral: u128,
+    /// Fee a trader with no volume yet would pay.
+    pub fee: u128,
+    /// `collateral / shares`, or 0 for an empty trade.
+    pub average_price: u128,
+    pub price_before: Price,
+    pub price_after: Price,
 }
 
-#[derive(Debug)]
-pub struct MarketState {
-    pub q_yes: u128,
-    pub q_no: u128,
+/// Per-market bounds on trading, in shares. `None` means unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeLimits {
+    /// Most shares a single buy or sell may trade.
+    pub max_trade: Option<u128>,
+    /// Most shares of any one outcome a trader may hold.
+    pub max_position: Option<u128>,
+}
+
+/// How well a market's collateral covers what it may have to pay out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Solvency {
+    /// Collateral the operator put up to cover the market maker's loss.
+    pub subsidy: u128,
+    /// Collateral traders have paid in for their shares.
     pub total_collateral: u128,
+    /// Most the market could owe if settled now: every share of the
+    /// most-held outcome. Once settled, what it paid out.
+    pub max_payout: u128,
+    /// What the market maker would lose if that outcome won now:
+    /// `max_payout` less `total_collateral`, or 0. Once settled, what it
+    /// lost.
+    pub current_loss: u128,
+    /// Bound on the market maker's loss over the market's life, set by the
+    /// liquidity function. Once settled, 0.
+    pub max_loss: u128,
+}
+
+/// What a market paid out when it was resolved or invalidated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settlement {
+    /// Collateral credited to traders for their shares.
+    pub paid: u128,
+    /// The part of `paid` the traders' collateral did not cover, taken out
+    /// of the subsidy.
+    pub loss: u128,
 }
 
-#[derive(Debug)]
+/// A serializable checkpoint of a `MarketEngine`, produced by
+/// `MarketEngine::snapshot` and restored with `MarketEngine::from_snapshot`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarketState<L> {
+    /// Snapshot format version, checked on restore.
+    pub version: u32,
+    pub liquidity: L,
+    pub quantities: Vec<u128>,
+    pub total_collateral: u128,
+    pub accounts: BTreeMap<TraderId, Account>,
+    pub sequence: u64,
+    pub status: MarketStatus,
+    pub close_time: Option<u64>,
+    pub now: u64,
+    pub limits: TradeLimits,
+    pub fees: FeeSchedule,
+    pub fees_collected: u128,
+    pub subsidy: u128,
+    pub settlement: Option<Settlement>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub enum TradeError {
     InvalidOutcome,
     InsufficientCollateral,
-}
\ No newline at end of file
+    InsufficientPosition,
+    MarketClosed,
+    MarketNotClosed,
+    MarketSettled,
+    /// The trade would cost more (or refund less) than the caller's limit.
+    SlippageExceeded { quoted: u128, actual: u128 },
+    /// The trade moves no shares.
+    ZeroAmount,
+    /// The trade moves more shares than the market's `max_trade`.
+    TradeTooLarge { max: u128 },
+    /// The trade would leave the trader holding more than `max_position`.
+    PositionLimitExceeded { max: u128 },
+    /// The trade would leave the market's collateral and subsidy short of
+    /// its largest possible payout.
+    Insolvent { shortfall: u128 },
+    /// A quantity, balance or cost would not fit in a u128.
+    Overflow,
+    /// The pricing math produced an impossible result, such as a fee larger
+    /// than the refund it is charged on.
+    NumericalError,
+    /// A snapshot was written by an incompatible version of the engine.
+    UnsupportedSnapshot { version: u32 },
+    /// A snapshot's accounts or status do not match its outcomes.
+    InvalidSnapshot,
+}
@@ -0,0 +1,98 @@
+// API-key authentication for trading and admin routes.
+//
+// Clients send `Authorization: Bearer <key>`. Each key maps to a trader and a
+// role: traders may buy and sell for themselves, admins may also create,
+// close and resolve markets, fund accounts, and trade for anyone. Reading
+// prices, history and quotes stays public.
+//
+// The config lists keys by their SHA-256 digest, so it holds nothing that
+// can be used to trade. With no keys configured authentication is off and
+// every route is open, as for local development; the server only starts
+// that way with `--insecure-no-auth`.
+use std::collections::HashMap;
+
+use lslmsr::types::TraderId;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tiny_http::Request;
+
+use crate::error::ApiError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Trader,
+    Admin,
+}
+
+/// Who a request was made by.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub trader: TraderId,
+    pub role: Role,
+}
+
+/// API keys, by the hex SHA-256 digest of the key.
+#[derive(Debug, Clone, Default)]
+pub struct Keys {
+    by_digest: HashMap<String, Principal>,
+}
+
+impl Keys {
+    /// Adds a key by its digest. False if the digest is already taken.
+    pub fn insert(&mut self, digest: &str, principal: Principal) -> bool {
+        self.by_digest.insert(digest.to_ascii_lowercase(), principal).is_none()
+    }
+
+    /// True if authentication is off.
+    pub fn is_empty(&self) -> bool {
+        self.by_digest.is_empty()
+    }
+
+    /// Checks that `request` carries a key with at least `role`. Returns who
+    /// it belongs to, or `None` if authentication is off.
+    pub fn authenticate(&self, request: &Request, role: Role) -> Result<Option<Principal>, ApiError> {
+        self.authorize(bearer(request), role)
+    }
+
+    /// Who `request` was made by, if it carries a valid key.
+    pub fn identify(&self, request: &Request) -> Option<&Principal> {
+        self.lookup(bearer(request)).ok()
+    }
+
+    /// `authenticate`, given the key from the request's bearer token.
+    pub fn authorize(&self, key: Option<&str>, role: Role) -> Result<Option<Principal>, ApiError> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+
+        let principal = self.lookup(key)?;
+        if principal.role < role {
+            return Err(ApiError::new(403, "forbidden", "This route needs an admin key")
+                .with_details(json!({ "trader": principal.trader })));
+        }
+        Ok(Some(principal.clone()))
+    }
+
+    fn lookup(&self, key: Option<&str>) -> Result<&Principal, ApiError> {
+        let key = key.ok_or_else(|| ApiError::new(401, "unauthorized", "Missing API key"))?;
+        self.by_digest
+            .get(&digest(key.trim()))
+            .ok_or_else(|| ApiError::new(401, "unauthorized", "Invalid API key"))
+    }
+}
+
+// The key in a request's `Authorization: Bearer` header, if any.
+fn bearer(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+}
+
+/// The hex SHA-256 digest of a key, as listed in the config.
+pub fn digest(key: &str) -> String {
+    Sha256::digest(key.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod tests {
+    use super::super::auth::*;
+
+    // SHA-256 of "secret" and "hunter2"
+    const SECRET: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+    const HUNTER2: &str = "f52fbd32b2b3b86ff88ef6c490628285f482af15ddcb29541f94bcf526a3f6c7";
+
+    fn keys() -> Keys {
+        let mut keys = Keys::default();
+        let alice = Principal { trader: "alice".to_string(), role: Role::Trader };
+        let operator = Principal { trader: "operator".to_string(), role: Role::Admin };
+        assert!(keys.insert(&SECRET.to_ascii_uppercase(), alice));
+        assert!(keys.insert(HUNTER2, operator));
+        keys
+    }
+
+    #[test]
+    fn test_digest_lookup() {
+        assert_eq!(digest("secret"), SECRET);
+
+        // Digests match whatever their case, and keys are trimmed
+        let keys = keys();
+        let principal = keys.authorize(Some(" secret "), Role::Trader).unwrap().unwrap();
+        assert_eq!((principal.trader.as_str(), principal.role), ("alice", Role::Trader));
+
+        let duplicate = Principal { trader: "bob".to_string(), role: Role::Trader };
+        assert!(!keys.clone().insert(SECRET, duplicate));
+    }
+
+    #[test]
+    fn test_roles() {
+        let keys = keys();
+
+        // Admins may use trader routes, but not the other way round
+        let operator = keys.authorize(Some("hunter2"), Role::Trader).unwrap().unwrap();
+        assert_eq!(operator.trader, "operator");
+        assert!(keys.authorize(Some("hunter2"), Role::Admin).is_ok());
+
+        let err = keys.authorize(Some("secret"), Role::Admin).unwrap_err();
+        assert_eq!((err.status, err.code), (403, "forbidden"));
+        assert_eq!(err.details["trader"], "alice");
+    }
+
+    #[test]
+    fn test_missing_or_wrong_key() {
+        let keys = keys();
+
+        let err = keys.authorize(None, Role::Trader).unwrap_err();
+        assert_eq!((err.status, err.code, err.message.as_str()), (401, "unauthorized", "Missing API key"));
+
+        let err = keys.authorize(Some("hunter3"), Role::Trader).unwrap_err();
+        assert_eq!((err.status, err.code, err.message.as_str()), (401, "unauthorized", "Invalid API key"));
+
+        // The digest itself is not a key
+        assert!(keys.authorize(Some(SECRET), Role::Trader).is_err());
+    }
+
+    #[test]
+    fn test_no_keys() {
+        // Only reachable with --insecure-no-auth: every request is let through
+        let keys = Keys::default();
+        assert!(keys.is_empty());
+        assert!(keys.authorize(None, Role::Admin).unwrap().is_none());
+    }
+}
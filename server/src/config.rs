@@ -0,0 +1,308 @@
+// Server configuration.
+//
+// Each setting comes from, in increasing order of precedence: its default,
+// the TOML file named by `--config`, the matching `LSLMSR_*` environment
+// variable, and the command-line flag. Everything is validated at startup,
+// so a bad value stops the server before it binds its socket.
+//
+// API keys and the default fee schedule are only read from the file, as
+// `[[keys]]` entries and a `[market.fees]` table. Without any keys the server
+// refuses to start unless told to run with authentication off, and then
+// listens on the loopback interface unless given an address.
+use std::fmt;
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+use log::LevelFilter;
+use lslmsr::fees::FeeSchedule;
+use lslmsr::journal::FsyncPolicy;
+use lslmsr::types::DECIMALS;
+use serde::Deserialize;
+
+use crate::auth::{Keys, Principal, Role};
+use crate::ratelimit::RateLimit;
+use crate::serve::Options;
+
+/// Command-line flags, each of which can also be set from the environment.
+/// Both override the config file.
+#[derive(Debug, Parser)]
+#[command(version, about = "Server for Liquidity-Sensitive LMSR prediction markets")]
+pub struct Args {
+    /// TOML config file
+    #[arg(short, long, env = "LSLMSR_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on [default: 0.0.0.0:8000, or 127.0.0.1:8000 with --insecure-no-auth]
+    #[arg(long, env = "LSLMSR_LISTEN")]
+    pub listen: Option<String>,
+    /// Run without API keys, leaving trading and admin routes open to anyone
+    #[arg(long, env = "LSLMSR_INSECURE_NO_AUTH")]
+    pub insecure_no_auth: bool,
+    /// Directory the web client is served from [default: client]
+    #[arg(long, env = "LSLMSR_STATIC_DIR")]
+    pub static_dir: Option<PathBuf>,
+    /// Directory holding market metadata and journals [default: markets]
+    #[arg(long, env = "LSLMSR_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    /// When journal records are synced: always, never, or every N records [default: always]
+    #[arg(long, env = "LSLMSR_FSYNC")]
+    pub fsync: Option<String>,
+    /// off, error, warn, info, debug or trace [default: info]
+    #[arg(long, env = "LSLMSR_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Number of request workers [default: one per CPU]
+    #[arg(long, env = "LSLMSR_WORKERS")]
+    pub workers: Option<usize>,
+    /// Request timeout in milliseconds [default: 10000]
+    #[arg(long, env = "LSLMSR_REQUEST_TIMEOUT_MS")]
+    pub request_timeout_ms: Option<u64>,
+    /// Largest accepted request body, in bytes [default: 65536]
+    #[arg(long, env = "LSLMSR_MAX_BODY_BYTES")]
+    pub max_body_bytes: Option<usize>,
+    /// Requests per second each client may make; 0 for no limit [default: 20]
+    #[arg(long, env = "LSLMSR_RATE_LIMIT")]
+    pub rate_limit: Option<u32>,
+    /// Requests a client may make in a burst, above the steady rate [default: 40]
+    #[arg(long, env = "LSLMSR_RATE_BURST")]
+    pub rate_burst: Option<u32>,
+    /// Alpha for new markets that do not set one, in fixed-point string form [default: 1.0]
+    #[arg(long, env = "LSLMSR_DEFAULT_ALPHA")]
+    pub default_alpha: Option<String>,
+    /// Most shares per trade in new markets that do not set it, in fixed-point string form [default: unlimited]
+    #[arg(long, env = "LSLMSR_DEFAULT_MAX_TRADE")]
+    pub default_max_trade: Option<String>,
+    /// Most shares of one outcome a trader may hold in new markets that do not set it [default: unlimited]
+    #[arg(long, env = "LSLMSR_DEFAULT_MAX_POSITION")]
+    pub default_max_position: Option<String>,
+    /// Subsidy for new markets that do not set one, in fixed-point string form [default: 0]
+    #[arg(long, env = "LSLMSR_DEFAULT_SUBSIDY")]
+    pub default_subsidy: Option<String>,
+}
+
+// The config file: the same settings as the flags, with market defaults
+// under `[market]` and API keys under `[[keys]]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    listen: Option<String>,
+    insecure_no_auth: Option<bool>,
+    static_dir: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    fsync: Option<String>,
+    log_level: Option<String>,
+    workers: Option<usize>,
+    request_timeout_ms: Option<u64>,
+    max_body_bytes: Option<usize>,
+    rate_limit: Option<u32>,
+    rate_burst: Option<u32>,
+    #[serde(default)]
+    market: MarketFile,
+    #[serde(default)]
+    keys: Vec<KeyFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MarketFile {
+    alpha: Option<String>,
+    outcomes: Option<Vec<String>>,
+    max_trade: Option<String>,
+    max_position: Option<String>,
+    fees: Option<crate::FeesRequest>,
+    subsidy: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    trader: String,
+    role: Option<Role>,
+    key_sha256: String,
+}
+
+/// Parameters for markets created without them.
+#[derive(Debug, Clone)]
+pub struct MarketDefaults {
+    pub alpha: u128,
+    pub outcomes: Vec<String>,
+    pub max_trade: Option<u128>,
+    pub max_position: Option<u128>,
+    pub fees: FeeSchedule,
+    pub subsidy: u128,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub listen: SocketAddr,
+    pub static_dir: PathBuf,
+    pub data_dir: PathBuf,
+    pub fsync: FsyncPolicy,
+    pub log_level: LevelFilter,
+    pub serve: Options,
+    pub rate_limit: RateLimit,
+    pub market: MarketDefaults,
+    /// Empty only if started with `--insecure-no-auth`.
+    pub keys: Keys,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file could not be read or parsed.
+    File { path: PathBuf, message: String },
+    /// A setting has an invalid value.
+    Invalid { key: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::File { path, message } => write!(f, "{}: {}", path.display(), message),
+            ConfigError::Invalid { key, message } => write!(f, "invalid {}: {}", key, message),
+        }
+    }
+}
+
+fn invalid(key: &'static str, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid { key, message: message.into() }
+}
+
+// Parses a setting given as a string, falling back to `default` if unset.
+fn parse<T: std::str::FromStr>(key: &'static str, value: Option<String>, default: T) -> Result<T, ConfigError> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| invalid(key, format!("{:?} is not a valid value", value))),
+        None => Ok(default),
+    }
+}
+
+// Parses an optional positive amount given as a string.
+fn limit(key: &'static str, value: Option<String>) -> Result<Option<u128>, ConfigError> {
+    value.map(|value| positive(key, parse(key, Some(value), 0)?)).transpose()
+}
+
+fn positive<T: PartialOrd + Default>(key: &'static str, value: T) -> Result<T, ConfigError> {
+    if value > T::default() {
+        Ok(value)
+    } else {
+        Err(invalid(key, "must be greater than zero"))
+    }
+}
+
+// The API keys listed in the config file.
+fn keys(list: Vec<KeyFile>) -> Result<Keys, ConfigError> {
+    let mut keys = Keys::default();
+    for key in list {
+        if key.trader.is_empty() {
+            return Err(invalid("keys.trader", "must not be empty"));
+        }
+        if key.key_sha256.len() != 64 || !key.key_sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid("keys.key_sha256", format!("{:?} is not a hex SHA-256 digest", key.key_sha256)));
+        }
+        let principal = Principal {
+            trader: key.trader,
+            role: key.role.unwrap_or(Role::Trader),
+        };
+        if !keys.insert(&key.key_sha256, principal) {
+            return Err(invalid("keys.key_sha256", format!("{} is listed twice", key.key_sha256)));
+        }
+    }
+    Ok(keys)
+}
+
+impl Config {
+    /// Builds the configuration from the process's flags, environment and
+    /// config file.
+    pub fn load() -> Result<Config, ConfigError> {
+        Config::from_args(Args::parse())
+    }
+
+    pub fn from_args(args: Args) -> Result<Config, ConfigError> {
+        let file: File = match &args.config {
+            Some(path) => {
+                let error = |message: String| ConfigError::File { path: path.clone(), message };
+                let contents = fs::read_to_string(path).map_err(|err| error(err.to_string()))?;
+                toml::from_str(&contents).map_err(|err| error(err.to_string()))?
+            }
+            None => File::default(),
+        };
+
+        let keys = keys(file.keys)?;
+        if keys.is_empty() && !(args.insecure_no_auth || file.insecure_no_auth == Some(true)) {
+            let message = "none configured; add [[keys]] or pass --insecure-no-auth to leave every route open";
+            return Err(invalid("keys", message));
+        }
+
+        // An open server only listens locally unless told otherwise
+        let default_listen = if keys.is_empty() { "127.0.0.1:8000" } else { "0.0.0.0:8000" };
+        let listen = args.listen.or(file.listen).unwrap_or_else(|| default_listen.to_string());
+        let listen = listen
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| invalid("listen", format!("{:?} is not an address", listen)))?;
+
+        let static_dir = args
+            .static_dir
+            .or(file.static_dir)
+            .unwrap_or_else(|| PathBuf::from("client"));
+        if !static_dir.join("index.html").is_file() {
+            return Err(invalid("static_dir", format!("{} has no index.html", static_dir.display())));
+        }
+
+        let data_dir = args.data_dir.or(file.data_dir).unwrap_or_else(|| PathBuf::from("markets"));
+        let fsync = parse("fsync", args.fsync.or(file.fsync), FsyncPolicy::Always)?;
+        let log_level = parse("log_level", args.log_level.or(file.log_level), LevelFilter::Info)?;
+
+        let default_workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+        let workers = args.workers.or(file.workers).unwrap_or(default_workers);
+        let request_timeout_ms = args.request_timeout_ms.or(file.request_timeout_ms).unwrap_or(10_000);
+        let max_body = args.max_body_bytes.or(file.max_body_bytes).unwrap_or(64 * 1024);
+        let serve = Options {
+            workers: positive("workers", workers)?,
+            request_timeout: Duration::from_millis(positive("request_timeout_ms", request_timeout_ms)?),
+            max_body: positive("max_body_bytes", max_body)?,
+        };
+
+        let rate_limit = RateLimit {
+            per_second: args.rate_limit.or(file.rate_limit).unwrap_or(20),
+            burst: positive("rate_burst", args.rate_burst.or(file.rate_burst).unwrap_or(40))?,
+        };
+
+        let alpha = parse("market.alpha", args.default_alpha.or(file.market.alpha), DECIMALS)?;
+        crate::validate_alpha(alpha).map_err(|err| invalid("market.alpha", err.message))?;
+        let outcomes = file
+            .market
+            .outcomes
+            .unwrap_or_else(|| vec!["YES".to_string(), "NO".to_string()]);
+        crate::validate_outcomes(&outcomes).map_err(|err| invalid("market.outcomes", err.message))?;
+        let max_trade = limit("market.max_trade", args.default_max_trade.or(file.market.max_trade))?;
+        let max_position = limit("market.max_position", args.default_max_position.or(file.market.max_position))?;
+        let fees = match file.market.fees {
+            Some(fees) => crate::fee_schedule(fees).map_err(|err| invalid("market.fees", err.message))?,
+            None => FeeSchedule::None,
+        };
+        let subsidy = parse("market.subsidy", args.default_subsidy.or(file.market.subsidy), 0)?;
+
+        Ok(Config {
+            listen,
+            static_dir,
+            data_dir,
+            fsync,
+            log_level,
+            serve,
+            rate_limit,
+            market: MarketDefaults {
+                alpha,
+                outcomes,
+                max_trade,
+                max_position,
+                fees,
+                subsidy,
+            },
+            keys,
+        })
+    }
+}
@@ -0,0 +1,39 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+
+    use super::super::config::*;
+
+    // Flags for a server run from the crate directory
+    fn config(flags: &[&str]) -> Result<Config, ConfigError> {
+        let args = ["lslmsr-server", "--static-dir", "../client"].iter().chain(flags);
+        Config::from_args(Args::parse_from(args))
+    }
+
+    #[test]
+    fn test_keys_are_required() {
+        assert!(matches!(config(&[]), Err(ConfigError::Invalid { key: "keys", .. })));
+
+        // Running without them only listens locally, unless told otherwise
+        let open = config(&["--insecure-no-auth"]).unwrap();
+        assert!(open.keys.is_empty());
+        assert_eq!(open.listen.to_string(), "127.0.0.1:8000");
+        let open = config(&["--insecure-no-auth", "--listen", "0.0.0.0:9000"]).unwrap();
+        assert_eq!(open.listen.to_string(), "0.0.0.0:9000");
+    }
+
+    #[test]
+    fn test_keys_from_file() {
+        let path = std::env::temp_dir().join(format!("lslmsr-config-{}.toml", std::process::id()));
+        let digest = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+        fs::write(&path, format!("[[keys]]\ntrader = \"alice\"\nkey_sha256 = \"{}\"\n", digest)).unwrap();
+
+        let secured = config(&["--config", path.to_str().unwrap()]).unwrap();
+        assert!(!secured.keys.is_empty());
+        assert_eq!(secured.listen.to_string(), "0.0.0.0:8000");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+// The JSON error envelope every failed request gets.
+//
+// A body looks like `{"error": {"code": ..., "message": ..., "details": ...}}`.
+// `code` is a stable snake_case identifier for clients to branch on,
+// `message` is for humans and may change, and `details` carries structured
+// context (such as the quoted and actual cost of a slippage failure) or is
+// null.
+use std::io::Cursor;
+
+use lslmsr::journal::JournalError;
+use lslmsr::types::TradeError;
+use serde_json::{json, Value};
+use tiny_http::{Header, Response, StatusCode};
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Value,
+    /// Seconds to send in a `Retry-After` header, if any.
+    pub retry_after: Option<u64>,
+}
+
+impl ApiError {
+    pub fn new(status: u16, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            details: Value::Null,
+            retry_after: None,
+        }
+    }
+
+    /// A malformed or invalid request.
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError::new(400, code, message)
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError::new(404, code, message)
+    }
+
+    /// A failure on our side; the request itself may well be fine.
+    pub fn internal(message: impl Into<String>) -> Self {
+        ApiError::new(500, "internal_error", message)
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    pub fn into_response(self) -> Response<Cursor<Vec<u8>>> {
+        let body = json!({
+            "error": {
+                "code": self.code,
+                "message": self.message,
+                "details": self.details
+            }
+        })
+        .to_string();
+
+        let mut response = Response::from_string(body)
+            .with_status_code(StatusCode(self.status))
+            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+        if let Some(seconds) = self.retry_after {
+            response.add_header(Header::from_bytes("Retry-After", seconds.to_string()).unwrap());
+        }
+        response
+    }
+}
+
+// A request body that is not JSON or does not match the route's fields
+impl From<serde_json::Error> for ApiError {
+    fn from(error: serde_json::Error) -> Self {
+        ApiError::bad_request("malformed_json", format!("Malformed JSON: {}", error))
+            .with_details(json!({ "line": error.line(), "column": error.column() }))
+    }
+}
+
+// The engine rejected an operation. Problems with the request are 4xx: 409
+// when the market's state forbids it, 422 when the trader's funds or limits
+// do. Anything that points at a bug is a 500.
+impl From<TradeError> for ApiError {
+    fn from(error: TradeError) -> Self {
+        match error {
+            TradeError::InvalidOutcome => ApiError::bad_request("invalid_outcome", "Invalid outcome"),
+            TradeError::InsufficientCollateral => {
+                ApiError::new(422, "insufficient_collateral", "Insufficient collateral")
+            }
+            TradeError::InsufficientPosition => {
+                ApiError::new(422, "insufficient_position", "Not enough shares to sell")
+            }
+            TradeError::MarketClosed => ApiError::new(409, "market_closed", "The market is closed to trading"),
+            TradeError::MarketNotClosed => {
+                ApiError::new(409, "market_not_closed", "The market must be closed first")
+            }
+            TradeError::MarketSettled => ApiError::new(409, "market_settled", "The market is already settled"),
+            TradeError::SlippageExceeded { quoted, actual } => {
+                ApiError::new(422, "slippage_exceeded", "The trade would exceed the price limit")
+                    .with_details(json!({ "quoted": quoted.to_string(), "actual": actual.to_string() }))
+            }
+            TradeError::ZeroAmount => ApiError::bad_request("zero_amount", "The trade must move at least one share"),
+            TradeError::TradeTooLarge { max } => {
+                ApiError::new(422, "trade_too_large", "The trade is larger than the market allows")
+                    .with_details(json!({ "max_trade": max.to_string() }))
+            }
+            TradeError::PositionLimitExceeded { max } => {
+                ApiError::new(422, "position_limit_exceeded", "The trade would exceed the position limit")
+                    .with_details(json!({ "max_position": max.to_string() }))
+            }
+            TradeError::Insolvent { shortfall } => {
+                ApiError::new(422, "market_insolvent", "The market's subsidy cannot cover this trade")
+                    .with_details(json!({ "shortfall": shortfall.to_string() }))
+            }
+            TradeError::Overflow => ApiError::new(422, "overflow", "The amount is too large"),
+            TradeError::NumericalError => ApiError::internal("The pricing math failed"),
+            TradeError::UnsupportedSnapshot { .. } | TradeError::InvalidSnapshot => {
+                ApiError::internal(format!("Snapshot error: {:?}", error))
+            }
+        }
+    }
+}
+
+impl From<JournalError> for ApiError {
+    fn from(error: JournalError) -> Self {
+        ApiError::internal(format!("Storage error: {:?}", error))
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+mod tests {
+    use lslmsr::types::TradeError;
+
+    use super::super::error::*;
+
+    #[test]
+    fn test_trade_error_codes() {
+        // Clients branch on these, so they must not change
+        let cases = [
+            (TradeError::InvalidOutcome, 400, "invalid_outcome"),
+            (TradeError::ZeroAmount, 400, "zero_amount"),
+            (TradeError::MarketClosed, 409, "market_closed"),
+            (TradeError::MarketNotClosed, 409, "market_not_closed"),
+            (TradeError::MarketSettled, 409, "market_settled"),
+            (TradeError::InsufficientCollateral, 422, "insufficient_collateral"),
+            (TradeError::InsufficientPosition, 422, "insufficient_position"),
+            (TradeError::SlippageExceeded { quoted: 1, actual: 2 }, 422, "slippage_exceeded"),
+            (TradeError::TradeTooLarge { max: 1 }, 422, "trade_too_large"),
+            (TradeError::PositionLimitExceeded { max: 1 }, 422, "position_limit_exceeded"),
+            (TradeError::Insolvent { shortfall: 1 }, 422, "market_insolvent"),
+            (TradeError::Overflow, 422, "overflow"),
+            (TradeError::NumericalError, 500, "internal_error"),
+            (TradeError::UnsupportedSnapshot { version: 0 }, 500, "internal_error"),
+            (TradeError::InvalidSnapshot, 500, "internal_error"),
+        ];
+        for (error, status, code) in cases {
+            let err = ApiError::from(error);
+            assert_eq!((err.status, err.code), (status, code));
+        }
+    }
+
+    #[test]
+    fn test_details() {
+        let err = ApiError::from(TradeError::SlippageExceeded { quoted: 50, actual: 52 });
+        assert_eq!(err.details["quoted"], "50");
+        assert_eq!(err.details["actual"], "52");
+
+        let err = ApiError::from(TradeError::TradeTooLarge { max: 10 });
+        assert_eq!(err.details["max_trade"], "10");
+        let err = ApiError::from(TradeError::PositionLimitExceeded { max: 15 });
+        assert_eq!(err.details["max_position"], "15");
+        let err = ApiError::from(TradeError::Insolvent { shortfall: 7 });
+        assert_eq!(err.details["shortfall"], "7");
+    }
+}
@@ -0,0 +1,193 @@
+// Live market events, pushed to clients as Server-Sent Events.
+//
+// Every accepted trade and status change is published to `Events`, which
+// fans it out to each subscribed stream over a bounded channel. Publishing
+// never blocks trading: a stream that falls too far behind is dropped, and
+// the client's EventSource reconnects and starts again from a fresh
+// snapshot.
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use lslmsr::types::{MarketStatus, Price, Side};
+
+use crate::registry::MarketId;
+use crate::schema::{side_name, to_json, Format, PriceEvent, StatusEvent, StatusView, TradeEvent};
+
+// Events a stream may fall behind by before it is dropped.
+const BACKLOG: usize = 256;
+
+// Most streams open at once.
+pub const MAX_STREAMS: usize = 1024;
+
+// An idle stream sends a comment this often, so proxies keep it open and
+// a closed connection is noticed.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A market's prices, as of its `sequence`th trade.
+    Price { market: MarketId, sequence: u64, prices: Price },
+    /// A trade, with the prices it left behind.
+    Trade {
+        market: MarketId,
+        sequence: u64,
+        time: u64,
+        side: Side,
+        outcome: usize,
+        shares: u128,
+        collateral: u128,
+        prices: Price,
+    },
+    /// A market closed or settled.
+    Status { market: MarketId, status: MarketStatus },
+}
+
+impl Event {
+    pub fn market(&self) -> MarketId {
+        match self {
+            Event::Price { market, .. } | Event::Trade { market, .. } | Event::Status { market, .. } => {
+                *market
+            }
+        }
+    }
+}
+
+struct Subscriber {
+    // `None` for every market
+    market: Option<MarketId>,
+    sender: SyncSender<Event>,
+    // Gone once the stream's `Subscription` is dropped
+    alive: Weak<()>,
+}
+
+/// A stream's end of a subscription. Dropping it unsubscribes, even from a
+/// market that never publishes again.
+pub struct Subscription {
+    receiver: Receiver<Event>,
+    _alive: Arc<()>,
+}
+
+#[derive(Default)]
+pub struct Events {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Events {
+    /// Subscribes to one market's events, or every market's. `None` if too
+    /// many streams are already open.
+    pub fn subscribe(&self, market: Option<MarketId>) -> Option<Subscription> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.alive.strong_count() > 0);
+        if subscribers.len() >= MAX_STREAMS {
+            return None;
+        }
+        let (sender, receiver) = mpsc::sync_channel(BACKLOG);
+        let alive = Arc::new(());
+        subscribers.push(Subscriber { market, sender, alive: Arc::downgrade(&alive) });
+        Some(Subscription { receiver, _alive: alive })
+    }
+
+    /// Sends `event` to every subscriber of its market, dropping those that
+    /// have gone away or fallen behind.
+    pub fn publish(&self, event: Event) {
+        let market = event.market();
+        self.subscribers.lock().unwrap().retain(|subscriber| {
+            if subscriber.market.is_some_and(|id| id != market) {
+                return true;
+            }
+            match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+}
+
+/// Writes a Server-Sent Events response to `writer`: the `snapshot` events
+/// first, then everything `subscription` delivers, until the client
+/// disconnects or the stream is dropped.
+pub fn stream(
+    mut writer: Box<dyn Write + Send>,
+    snapshot: Vec<Event>,
+    subscription: Subscription,
+    format: Format,
+) {
+    // tiny_http would buffer a streamed body, so write the response by hand.
+    // Without a length the body runs until the connection closes.
+    let head = "HTTP/1.1 200 OK\r\n\
+        Content-Type: text/event-stream\r\n\
+        Cache-Control: no-cache\r\n\
+        Connection: close\r\n\r\n";
+    let result = (|| -> io::Result<()> {
+        writer.write_all(head.as_bytes())?;
+        for event in &snapshot {
+            write_event(&mut writer, event, format)?;
+        }
+        writer.flush()?;
+
+        loop {
+            match subscription.receiver.recv_timeout(KEEPALIVE) {
+                Ok(event) => write_event(&mut writer, &event, format)?,
+                Err(RecvTimeoutError::Timeout) => writer.write_all(b": keepalive\n\n")?,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            writer.flush()?;
+        }
+    })();
+
+    if let Err(err) = result {
+        log::debug!("event stream closed: {}", err);
+    }
+}
+
+fn write_event(writer: &mut dyn Write, event: &Event, format: Format) -> io::Result<()> {
+    match event {
+        Event::Price { market, sequence, prices } => {
+            let data = to_json(&PriceEvent {
+                market: *market,
+                sequence: *sequence,
+                prices: format.prices(prices),
+            });
+            write!(writer, "event: price\ndata: {}\n\n", data)
+        }
+        Event::Trade {
+            market,
+            sequence,
+            time,
+            side,
+            outcome,
+            shares,
+            collateral,
+            prices,
+        } => {
+            let data = to_json(&TradeEvent {
+                market: *market,
+                sequence: *sequence,
+                time: *time,
+                side: side_name(*side),
+                outcome: *outcome,
+                shares: format.amount(*shares),
+                collateral: format.amount(*collateral),
+                prices: format.prices(prices),
+            });
+            write!(writer, "event: trade\ndata: {}\n\n", data)?;
+
+            // Price listeners need not parse trades
+            let price = Event::Price {
+                market: *market,
+                sequence: *sequence,
+                prices: prices.clone(),
+            };
+            write_event(writer, &price, format)
+        }
+        Event::Status { market, status } => {
+            let data = to_json(&StatusEvent {
+                market: *market,
+                status: StatusView::new(*status),
+            });
+            write!(writer, "event: status\ndata: {}\n\n", data)
+        }
+    }
+}
@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    use lslmsr::types::MarketStatus;
+
+    use super::super::events::*;
+    use super::super::schema::Format;
+
+    // A writer whose output the test can read once the stream ends
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn status(market: u64) -> Event {
+        Event::Status { market, status: MarketStatus::Resolved(0) }
+    }
+
+    #[test]
+    fn test_closed_streams_free_their_slot() {
+        let events = Events::default();
+
+        // A resolved market never publishes again, so its streams must be
+        // dropped on the way in rather than by a later event
+        for _ in 0..2 * MAX_STREAMS {
+            drop(events.subscribe(Some(1)).unwrap());
+        }
+        assert!(events.subscribe(Some(1)).is_some());
+    }
+
+    #[test]
+    fn test_open_streams_are_limited() {
+        let events = Events::default();
+        let mut open: Vec<_> = (0..MAX_STREAMS).map(|_| events.subscribe(None).unwrap()).collect();
+        assert!(events.subscribe(Some(1)).is_none());
+
+        open.pop();
+        assert!(events.subscribe(Some(1)).is_some());
+    }
+
+    #[test]
+    fn test_stream_receives_its_market() {
+        let events = Events::default();
+        let subscription = events.subscribe(Some(1)).unwrap();
+        events.publish(status(2));
+        events.publish(status(1));
+
+        // Dropping every sender ends the stream once it has caught up
+        drop(events);
+        let output = Output::default();
+        stream(Box::new(output.clone()), vec![], subscription, Format::Fixed);
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(output.matches("event: status\n").count(), 1);
+        assert!(output.contains("\"market\":1,"));
+    }
+}
@@ -1,224 +1,669 @@
-use tiny_http::{Server, Response, Method, StatusCode, Header};
-use serde_json::json;
-use std::sync::{Arc, Mutex};
-use lslmsr::market::MarketEngine;
+use tiny_http::{Server, Request, Response, Method, StatusCode, Header};
+use serde_json::{json, Value};
+use std::process;
+use std::sync::{Arc, Mutex, MutexGuard};
+use lslmsr::fees::{FeeSchedule, FeeTier, BPS};
+use lslmsr::journal::Entry;
+use lslmsr::lslmsr::{MAX_ALPHA, MIN_ALPHA};
+use lslmsr::types::Side;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
-use std::io::Read;
+
+mod auth;
+mod config;
+mod error;
+mod events;
+mod ratelimit;
+mod registry;
+mod schema;
+mod serve;
+
+#[cfg(test)]
+mod auth_test;
+#[cfg(test)]
+mod config_test;
+#[cfg(test)]
+mod error_test;
+#[cfg(test)]
+mod events_test;
+#[cfg(test)]
+mod main_test;
+#[cfg(test)]
+mod ratelimit_test;
+#[cfg(test)]
+mod registry_test;
+
+use auth::{Principal, Role};
+use config::Config;
+use error::ApiError;
+use ratelimit::RateLimiter;
+use registry::{Market, MarketId, MarketInfo, Registry};
+use schema::{
+    query_param, to_json, BuyResponse, CandleView, DepositResponse, Format, HistoryResponse, MarketList,
+    MarketView, PriceResponse, QuoteResponse, ReceiptView, ResolveResponse, SellResponse, SolvencyResponse,
+    StatusResponse, StatusView, WithdrawFeesResponse,
+};
+use serve::{Context, HttpResponse, Reply};
+
+#[derive(Deserialize)]
+struct CreateMarketRequest {
+    question: String,
+    outcomes: Option<Vec<String>>, // outcome names, defaults to the configured ones
+    alpha: Option<String>,         // in fixed-point string form, defaults to the configured one
+    close_time: Option<u64>,       // unix time in seconds
+    max_trade: Option<String>,     // most shares per trade, defaults to the configured limit
+    max_position: Option<String>,  // most shares of one outcome per trader, likewise
+    fees: Option<FeesRequest>,     // fee schedule, defaults to the configured one
+    subsidy: Option<String>,       // collateral covering the market maker's loss, likewise
+}
+
+// A fee schedule, as given when creating a market or in the config file.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum FeesRequest {
+    None,
+    Flat { bps: u32 },
+    PriceDependent { bps: u32 },
+    Tiered { tiers: Vec<FeeTierRequest> },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FeeTierRequest {
+    min_volume: String, // collateral already traded, in fixed-point string form
+    bps: u32,
+}
+
+// Converts a requested fee schedule, rejecting rates above 100%.
+fn fee_schedule(fees: FeesRequest) -> Result<FeeSchedule, ApiError> {
+    let schedule = match fees {
+        FeesRequest::None => FeeSchedule::None,
+        FeesRequest::Flat { bps } => FeeSchedule::Flat { bps },
+        FeesRequest::PriceDependent { bps } => FeeSchedule::PriceDependent { bps },
+        FeesRequest::Tiered { tiers } => FeeSchedule::Tiered {
+            tiers: tiers
+                .into_iter()
+                .map(|tier| {
+                    Ok(FeeTier {
+                        min_volume: parse_amount("min_volume", &tier.min_volume)?,
+                        bps: tier.bps,
+                    })
+                })
+                .collect::<Result<_, ApiError>>()?,
+        },
+    };
+    if !schedule.is_valid() {
+        return Err(ApiError::bad_request("invalid_fees", format!("Fee rates must be at most {} bps", BPS)));
+    }
+    Ok(schedule)
+}
+
+// Outcome names must be distinct and leave "INVALID" free for /resolve.
+fn validate_outcomes(outcomes: &[String]) -> Result<(), ApiError> {
+    if outcomes.len() < 2 {
+        return Err(ApiError::bad_request("too_few_outcomes", "A market needs at least two outcomes"));
+    }
+    for (i, name) in outcomes.iter().enumerate() {
+        if name.is_empty() || name.eq_ignore_ascii_case("INVALID") {
+            return Err(ApiError::bad_request("invalid_outcome_name", "Invalid outcome name")
+                .with_details(json!({ "outcome": name })));
+        }
+        if outcomes[..i].iter().any(|other| other.eq_ignore_ascii_case(name)) {
+            return Err(ApiError::bad_request("duplicate_outcome", "Duplicate outcome name")
+                .with_details(json!({ "outcome": name })));
+        }
+    }
+    Ok(())
+}
+
+// Alpha must keep b positive and within a u128 for every trade size.
+fn validate_alpha(alpha: u128) -> Result<(), ApiError> {
+    if !(MIN_ALPHA..=MAX_ALPHA).contains(&alpha) {
+        let message = format!("Alpha must be between {} and {}", MIN_ALPHA, MAX_ALPHA);
+        return Err(ApiError::bad_request("invalid_alpha", message).with_details(json!({
+            "min": MIN_ALPHA.to_string(),
+            "max": MAX_ALPHA.to_string(),
+        })));
+    }
+    Ok(())
+}
 
 #[derive(Deserialize)]
 struct BuyRequest {
-    outcome: String, // "YES" or "NO"
-    amount: String,  // in fixed-point string form, e.g. "1000000000000000000"
+    trader: Option<String>,     // for /buy and /sell; defaults to the API key's trader
+    outcome: String,            // outcome name (e.g. "YES") or index
+    amount: Option<String>,     // shares, in fixed-point string form, e.g. "1000000000000000000"
+    collateral: Option<String>, // or a collateral budget (/buy and /simulate only)
+    max_cost: Option<String>,   // reject a buy that would cost more than this
+    min_refund: Option<String>, // reject a sell that would refund less than this
+    side: Option<String>,       // "buy" (default) or "sell", /simulate only
+}
+
+// Which side of the book a /simulate request quotes.
+fn trade_side(request: &BuyRequest) -> Result<Side, ApiError> {
+    match request.side.as_deref().map(str::to_lowercase).as_deref() {
+        None | Some("buy") => Ok(Side::Buy),
+        Some("sell") => Ok(Side::Sell),
+        Some(_) => Err(ApiError::bad_request("invalid_side", "Side must be \"buy\" or \"sell\"")),
+    }
+}
+
+enum TradeSize {
+    Shares(u128),
+    Collateral(u128),
+}
+
+// Exactly one of `amount` and `collateral` must be given.
+fn trade_size(request: &BuyRequest) -> Result<TradeSize, ApiError> {
+    match (&request.amount, &request.collateral) {
+        (Some(amount), None) => parse_amount("amount", amount).map(TradeSize::Shares),
+        (None, Some(collateral)) => parse_amount("collateral", collateral).map(TradeSize::Collateral),
+        _ => Err(ApiError::bad_request(
+            "invalid_trade_size",
+            "Specify exactly one of amount or collateral",
+        )),
+    }
+}
+
+// Sells are sized in shares only.
+fn sell_size(request: &BuyRequest) -> Result<u128, ApiError> {
+    match trade_size(request)? {
+        TradeSize::Shares(amount) => Ok(amount),
+        TradeSize::Collateral(_) => Err(ApiError::bad_request(
+            "invalid_trade_size",
+            "Sells take an amount of shares",
+        )),
+    }
+}
+
+// Parses a fixed-point string field, naming the field on failure.
+fn parse_amount(field: &'static str, value: &str) -> Result<u128, ApiError> {
+    value.parse().map_err(|_| {
+        ApiError::bad_request("invalid_number", format!("Invalid {}", field))
+            .with_details(json!({ "field": field }))
+    })
+}
+
+// Parses an optional fixed-point limit, rejecting malformed values.
+fn parse_limit(field: &'static str, value: &Option<String>) -> Result<Option<u128>, ApiError> {
+    value.as_deref().map(|value| parse_amount(field, value)).transpose()
+}
+
+// Parses an optional positive limit on a new market.
+fn parse_max(field: &'static str, value: &Option<String>) -> Result<Option<u128>, ApiError> {
+    match parse_limit(field, value)? {
+        Some(0) => Err(ApiError::bad_request("invalid_number", format!("{} must be positive", field))
+            .with_details(json!({ "field": field }))),
+        max => Ok(max),
+    }
+}
+
+// The trader a /buy or /sell acts for. A trader's key only acts for its own
+// trader; an admin's acts for whoever the request names, or itself.
+fn acting_trader(principal: Option<&Principal>, request: &BuyRequest) -> Result<String, ApiError> {
+    match (principal, &request.trader) {
+        (None, Some(trader)) => Ok(trader.clone()),
+        (None, None) => Err(ApiError::bad_request("missing_trader", "Missing trader")),
+        (Some(principal), None) => Ok(principal.trader.clone()),
+        (Some(principal), Some(trader)) if principal.role == Role::Admin || *trader == principal.trader => {
+            Ok(trader.clone())
+        }
+        (Some(_), Some(trader)) => Err(ApiError::new(403, "trader_mismatch", "API key belongs to another trader")
+            .with_details(json!({ "trader": trader }))),
+    }
+}
+
+fn outcome_index(market: &Market, outcome: &str) -> Result<usize, ApiError> {
+    market.outcome_index(outcome).ok_or_else(|| {
+        ApiError::bad_request("invalid_outcome", "Invalid outcome").with_details(json!({ "outcome": outcome }))
+    })
+}
+
+// Parses a candle interval such as "30s", "5m", "1h" or "1d"; a bare number
+// is in seconds.
+fn parse_interval(value: &str) -> Result<u64, ApiError> {
+    let (count, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+    count
+        .parse::<u64>()
+        .ok()
+        .and_then(|count| count.checked_mul(seconds))
+        .filter(|&interval| interval > 0)
+        .ok_or_else(|| {
+            ApiError::bad_request("invalid_interval", "Interval must look like 30s, 5m, 1h or 1d")
+                .with_details(json!({ "interval": value }))
+        })
+}
+
+// Parses an optional unix-time query parameter.
+fn parse_time(query: &str, field: &'static str) -> Result<Option<u64>, ApiError> {
+    query_param(query, field)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                ApiError::bad_request("invalid_number", format!("Invalid {}", field))
+                    .with_details(json!({ "field": field }))
+            })
+        })
+        .transpose()
+}
+
+#[derive(Deserialize)]
+struct ResolveRequest {
+    outcome: String, // outcome name or "INVALID"
+}
+
+#[derive(Deserialize)]
+struct WithdrawFeesRequest {
+    amount: Option<String>, // in fixed-point string form, defaults to every collected fee
+}
+
+#[derive(Deserialize)]
+struct DepositRequest {
+    trader: String,
+    amount: String, // in fixed-point string form
+}
+
+// Locks a market and brings its clock up to date. A market left poisoned by
+// a panicking request is first rebuilt from its journal.
+fn lock<'a>(context: &Context, market: &'a Arc<Mutex<Market>>) -> Result<MutexGuard<'a, Market>, ApiError> {
+    let mut market = context.lock(market, |market| match market.reload() {
+        Ok(()) => {
+            log::warn!("Market {}: reloaded from its journal after an internal error", market.info.id);
+            true
+        }
+        Err(err) => {
+            log::error!("Market {}: failed to reload its journal: {:?}", market.info.id, err);
+            false
+        }
+    })?;
+    market.tick();
+    Ok(market)
+}
+
+// Reads and parses a JSON request body.
+fn read_json<T: DeserializeOwned>(request: &mut Request, context: &Context) -> Result<T, ApiError> {
+    Ok(serde_json::from_str(&context.read_body(request)?)?)
+}
+
+fn json_response(status: u16, body: Value) -> HttpResponse {
+    Response::from_string(body.to_string())
+        .with_status_code(StatusCode(status))
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
 }
 
 fn main() {
-    let server = Server::http("0.0.0.0:8000").unwrap();
-    let market = Arc::new(Mutex::new(MarketEngine::new(1_000_000_000_000_000_000)));
-
-    println!("Server running at http://0.0.0.0:8000");
-
-    for mut request in server.incoming_requests() {
-        println!("received request! method: {:?}, url: {:?}, headers: {:?}",
-            request.method(),
-            request.url(),
-            request.headers()
-        );
-
-        let method = request.method();
-        let path = request.url();
-        let market = Arc::clone(&market);
-
-        match (method, path) {
-            (&Method::Get, "/price") => {
-                let engine = market.lock().unwrap();
-                let price = engine.get_price();
-                let body = json!({
-                    "yes": price.yes.to_string(),
-                    "no": price.no.to_string()
-                })
-                .to_string();
-
-                let content_type = Header::from_bytes("Content-Type", "application/json")
-                    .unwrap();
-                let response = Response::from_string(body)
-                    .with_header(content_type);
-                request.respond(response).unwrap();
-            }
-
-            (&Method::Get, "/") => {
-                use std::fs;
-
-                match fs::read_to_string("./client/index.html") {
-                    Ok(contents) => {
-                        let response = Response::from_string(contents)
-                            .with_header(Header::from_bytes("Content-Type", "text/html").unwrap());
-                        request.respond(response).unwrap();
-                    }
-                    Err(_) => {
-                        let response = Response::from_string("index.html not found")
-                            .with_status_code(StatusCode(500));
-                        request.respond(response).unwrap();
-                    }
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(2);
+        }
+    };
+    env_logger::Builder::new().filter_level(config.log_level).init();
+
+    // Rebuild every market from the operations accepted before the last shutdown
+    let registry = Registry::open(&config.data_dir, config.fsync).unwrap_or_else(|err| {
+        log::error!("failed to load markets from {}: {:?}", config.data_dir.display(), err);
+        process::exit(1);
+    });
+
+    let shutdown = serve::shutdown_flag().expect("failed to install signal handlers");
+    let server = Server::http(config.listen).unwrap_or_else(|err| {
+        log::error!("failed to listen on {}: {}", config.listen, err);
+        process::exit(1);
+    });
+
+    if config.keys.is_empty() {
+        log::warn!("No API keys configured; trading and admin routes are open to anyone");
+    }
+    let limiter = RateLimiter::new(config.rate_limit);
+    log::info!("Server running at http://{} with {} workers", config.listen, config.serve.workers);
+
+    serve::run(&server, &config.serve, &shutdown, |request, context| {
+        handle(&config, &registry, &limiter, request, context)
+    });
+
+    // Make sure every acknowledged operation is on disk, whatever the fsync policy
+    log::info!("Shutting down");
+    if let Err(err) = registry.sync() {
+        log::error!("failed to sync trade journals: {}", err);
+        process::exit(1);
+    }
+}
+
+fn handle(
+    config: &Config,
+    registry: &Registry,
+    limiter: &RateLimiter,
+    request: &mut Request,
+    context: &Context,
+) -> Result<Reply, ApiError> {
+    let client = match (config.keys.identify(request), request.remote_addr()) {
+        (Some(principal), _) => format!("trader:{}", principal.trader),
+        (None, Some(addr)) => format!("ip:{}", addr.ip()),
+        (None, None) => "unknown".to_string(),
+    };
+    limiter.check(&client)?;
+
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let format = Format::from_query(query)?;
+
+    match (&method, segments.as_slice()) {
+        (&Method::Get, [""]) => {
+            let contents = std::fs::read_to_string(config.static_dir.join("index.html"))
+                .map_err(|_| ApiError::internal("index.html not found"))?;
+            Ok(Response::from_string(contents)
+                .with_header(Header::from_bytes("Content-Type", "text/html").unwrap())
+                .into())
+        }
+
+        (&Method::Get, ["markets"]) => {
+            let markets = registry
+                .list()
+                .iter()
+                .map(|market| MarketView::new(&*lock(context, market)?, format))
+                .collect::<Result<_, ApiError>>()?;
+            Ok(json_response(200, to_json(&MarketList { markets })).into())
+        }
+
+        (&Method::Get, ["stream"]) => stream(registry, None, context, format),
+
+        (&Method::Post, ["markets"]) => {
+            config.keys.authenticate(request, Role::Admin)?;
+            Ok(create_market(config, registry, read_json(request, context)?, context, format)?.into())
+        }
+
+        (_, ["markets", id, action]) => {
+            let (id, market) = id
+                .parse()
+                .ok()
+                .and_then(|id| Some((id, registry.get(id)?)))
+                .ok_or_else(|| {
+                    ApiError::not_found("market_not_found", "Market not found").with_details(json!({ "id": id }))
+                })?;
+
+            // Trading needs a key; changing the market or funding accounts
+            // needs an admin's
+            let principal = match (&method, *action) {
+                (&Method::Post, "buy" | "sell") => config.keys.authenticate(request, Role::Trader)?,
+                (&Method::Post, "deposit" | "close" | "resolve" | "withdraw-fees") => {
+                    config.keys.authenticate(request, Role::Admin)?
                 }
-            }
-
-            (&Method::Post, "/buy") => {
-                let mut body = String::new();
-                request.as_reader().read_to_string(&mut body).unwrap();
-            
-                let parsed: Result<BuyRequest, _> = serde_json::from_str(&body);
-                if let Ok(buy) = parsed {
-                    let outcome = match buy.outcome.to_uppercase().as_str() {
-                        "YES" => lslmsr::types::Outcome::Yes,
-                        "NO" => lslmsr::types::Outcome::No,
-                        _ => {
-                            let response = Response::from_string("Invalid outcome")
-                                .with_status_code(StatusCode(400));
-                            request.respond(response).unwrap();
-                            return;
-                        }
-                    };
-            
-                    let amount: u128 = match buy.amount.parse() {
-                        Ok(val) => val,
-                        Err(_) => {
-                            let response = Response::from_string("Invalid amount")
-                                .with_status_code(StatusCode(400));
-                            request.respond(response).unwrap();
-                            return;
-                        }
-                    };
-            
-                    let mut engine = market.lock().unwrap();
-                    match engine.buy(outcome, amount) {
-                        Ok(new_price) => {
-                            let body = json!({
-                                "yes": (new_price.yes as f64 / 1e18),
-                                "no": (new_price.no as f64 / 1e18)
-                            })
-                            .to_string();
-            
-                            let response = Response::from_string(body)
-                                .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
-                            request.respond(response).unwrap();
-                        }
-                        Err(err) => {
-                            let response = Response::from_string(format!("Trade failed: {:?}", err))
-                                .with_status_code(StatusCode(500));
-                            request.respond(response).unwrap();
-                        }
-                    }
-                } else {
-                    let response = Response::from_string("Malformed JSON")
-                        .with_status_code(StatusCode(400));
-                    request.respond(response).unwrap();
+                _ => None,
+            };
+
+            let body = match (&method, *action) {
+                (&Method::Get, "price") => {
+                    let market = lock(context, &market)?;
+                    to_json(&PriceResponse {
+                        outcomes: market.info.outcomes.clone(),
+                        prices: format.prices(&market.engine.get_price()?),
+                    })
                 }
-            }
-
-            (&Method::Post, "/sell") => {
-                let mut body = String::new();
-                request.as_reader().read_to_string(&mut body).unwrap();
-
-                let parsed: Result<BuyRequest, _> = serde_json::from_str(&body);
-                if let Ok(sell) = parsed {
-                    let outcome = match sell.outcome.to_uppercase().as_str() {
-                        "YES" => lslmsr::types::Outcome::Yes,
-                        "NO" => lslmsr::types::Outcome::No,
-                        _ => {
-                            let response = Response::from_string("Invalid outcome")
-                                .with_status_code(StatusCode(400));
-                            request.respond(response).unwrap();
-                            return;
-                        }
-                    };
-
-                    let amount: u128 = match sell.amount.parse() {
-                        Ok(val) => val,
-                        Err(_) => {
-                            let response = Response::from_string("Invalid amount")
-                                .with_status_code(StatusCode(400));
-                            request.respond(response).unwrap();
-                            return;
-                        }
-                    };
-
-                    let mut engine = market.lock().unwrap();
-                    match engine.sell(outcome, amount) {
-                        Ok(new_price) => {
-                            let body = json!({
-                                "yes": (new_price.yes as f64 / 1e18),
-                                "no": (new_price.no as f64 / 1e18)
-                            })
-                            .to_string();
-
-                            let response = Response::from_string(body)
-                                .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
-                            request.respond(response).unwrap();
-                        }
-                        Err(err) => {
-                            let response = Response::from_string(format!("Sell failed: {:?}", err))
-                                .with_status_code(StatusCode(500));
-                            request.respond(response).unwrap();
-                        }
-                    }
-                } else {
-                    let response = Response::from_string("Malformed JSON")
-                        .with_status_code(StatusCode(400));
-                    request.respond(response).unwrap();
+                (&Method::Get, "solvency") => {
+                    let market = lock(context, &market)?;
+                    to_json(&SolvencyResponse::new(&market.engine.solvency(), format))
                 }
-            }
-
-            
-            (&Method::Post, "/simulate") => {
-                let mut body = String::new();
-                request.as_reader().read_to_string(&mut body).unwrap();
-
-                let parsed: Result<BuyRequest, _> = serde_json::from_str(&body);
-                if let Ok(buy) = parsed {
-                    let outcome = match buy.outcome.to_uppercase().as_str() {
-                        "YES" => lslmsr::types::Outcome::Yes,
-                        "NO" => lslmsr::types::Outcome::No,
-                        _ => {
-                            let response = Response::from_string("Invalid outcome")
-                                .with_status_code(StatusCode(400));
-                            request.respond(response).unwrap();
-                            return;
-                        }
-                    };
-
-                    let amount: u128 = match buy.amount.parse() {
-                        Ok(val) => val,
-                        Err(_) => {
-                            let response = Response::from_string("Invalid amount")
-                                .with_status_code(StatusCode(400));
-                            request.respond(response).unwrap();
-                            return;
-                        }
-                    };
-
-                    let engine = market.lock().unwrap();
-                    let cost = engine.simulate(outcome, amount);
-
-                    let body = json!({
-                        "simulated_cost": cost.to_string(),
-                        "simulated_cost_float": (cost as f64 / 1e18)
-                    })
-                    .to_string();
-
-                    let response = Response::from_string(body)
-                        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
-                    request.respond(response).unwrap();
-                } else {
-                    let response = Response::from_string("Malformed JSON")
-                        .with_status_code(StatusCode(400));
-                    request.respond(response).unwrap();
+                (&Method::Get, "history") => history(&market, query, context, format)?,
+                (&Method::Get, "stream") => return stream(registry, Some((id, &market)), context, format),
+                (&Method::Post, "buy") => buy(&market, principal.as_ref(), read_json(request, context)?, context, format)?,
+                (&Method::Post, "sell") => sell(&market, principal.as_ref(), read_json(request, context)?, context, format)?,
+                (&Method::Post, "deposit") => deposit(&market, read_json(request, context)?, context, format)?,
+                (&Method::Post, "close") => {
+                    let mut market = lock(context, &market)?;
+                    market.engine.close()?;
+                    market.record(Entry::Close);
+                    to_json(&StatusResponse { status: StatusView::new(market.engine.status) })
                 }
-            }
+                (&Method::Post, "resolve") => resolve(&market, read_json(request, context)?, context, format)?,
+                (&Method::Post, "withdraw-fees") => withdraw_fees(&market, read_json(request, context)?, context, format)?,
+                (&Method::Post, "simulate") => simulate(&market, read_json(request, context)?, context, format)?,
+                _ => return Err(ApiError::not_found("not_found", "No such route")),
+            };
+            Ok(json_response(200, body).into())
+        }
+
+        _ => Err(ApiError::not_found("not_found", "No such route")),
+    }
+}
 
-            _ => {
-                let response = Response::empty(StatusCode(404));
-                request.respond(response).unwrap();
-            }
+fn create_market(
+    config: &Config,
+    registry: &Registry,
+    create: CreateMarketRequest,
+    context: &Context,
+    format: Format,
+) -> Result<HttpResponse, ApiError> {
+    let outcomes = create.outcomes.unwrap_or_else(|| config.market.outcomes.clone());
+    validate_outcomes(&outcomes)?;
+
+    let alpha = match create.alpha {
+        Some(alpha) => parse_amount("alpha", &alpha)?,
+        None => config.market.alpha,
+    };
+    validate_alpha(alpha)?;
+
+    let max_trade = parse_max("max_trade", &create.max_trade)?.or(config.market.max_trade);
+    let max_position = parse_max("max_position", &create.max_position)?.or(config.market.max_position);
+    let fees = match create.fees {
+        Some(fees) => fee_schedule(fees)?,
+        None => config.market.fees.clone(),
+    };
+    let subsidy = parse_limit("subsidy", &create.subsidy)?.unwrap_or(config.market.subsidy);
+
+    let info = MarketInfo {
+        id: 0,
+        question: create.question,
+        outcomes,
+        alpha,
+        close_time: create.close_time,
+        max_trade,
+        max_position,
+        fees,
+        subsidy,
+    };
+    let market = registry.create(info)?;
+    let body = to_json(&MarketView::new(&*lock(context, &market)?, format)?);
+    Ok(json_response(201, body))
+}
+
+// Opens a Server-Sent Events stream of one market's events, or every
+// market's. The stream subscribes before taking its snapshot, so no trade
+// falls between the two; clients skip events whose sequence is not past the
+// snapshot's.
+fn stream(
+    registry: &Registry,
+    market: Option<(MarketId, &Arc<Mutex<Market>>)>,
+    context: &Context,
+    format: Format,
+) -> Result<Reply, ApiError> {
+    let subscription = registry
+        .events()
+        .subscribe(market.map(|(id, _)| id))
+        .ok_or_else(|| ApiError::new(503, "too_many_streams", "Too many event streams are open"))?;
+
+    let markets = match market {
+        Some((_, market)) => vec![Arc::clone(market)],
+        None => registry.list(),
+    };
+    let snapshot = markets
+        .iter()
+        .map(|market| Ok(lock(context, market)?.snapshot()?))
+        .collect::<Result<_, ApiError>>()?;
+
+    Ok(Reply::Stream(Box::new(move |writer| events::stream(writer, snapshot, subscription, format))))
+}
+
+fn buy(
+    market: &Arc<Mutex<Market>>,
+    principal: Option<&Principal>,
+    buy: BuyRequest,
+    context: &Context,
+    format: Format,
+) -> Result<Value, ApiError> {
+    let size = trade_size(&buy)?;
+    let trader = acting_trader(principal, &buy)?;
+    let max_cost = parse_limit("max_cost", &buy.max_cost)?;
+
+    let mut market = lock(context, market)?;
+    let outcome = outcome_index(&market, &buy.outcome)?;
+
+    let receipt = match size {
+        TradeSize::Shares(amount) => market.engine.buy(&trader, outcome, amount, max_cost)?,
+        TradeSize::Collateral(budget) => {
+            let budget = max_cost.map_or(budget, |limit| budget.min(limit));
+            market.engine.buy_with_collateral(&trader, outcome, budget)?
         }
+    };
+    market.record(Entry::Buy {
+        trader: receipt.trader.clone(),
+        outcome: receipt.outcome,
+        shares: receipt.shares,
+        cost: receipt.collateral,
+    });
+
+    Ok(to_json(&BuyResponse {
+        shares: format.amount(receipt.shares),
+        prices: format.prices(&receipt.price_after),
+        receipt: ReceiptView::new(&receipt, format),
+    }))
+}
+
+fn sell(
+    market: &Arc<Mutex<Market>>,
+    principal: Option<&Principal>,
+    sell: BuyRequest,
+    context: &Context,
+    format: Format,
+) -> Result<Value, ApiError> {
+    let amount = sell_size(&sell)?;
+    let trader = acting_trader(principal, &sell)?;
+    let min_refund = parse_limit("min_refund", &sell.min_refund)?;
+
+    let mut market = lock(context, market)?;
+    let outcome = outcome_index(&market, &sell.outcome)?;
+
+    let receipt = market.engine.sell(&trader, outcome, amount, min_refund)?;
+    market.record(Entry::Sell {
+        trader: receipt.trader.clone(),
+        outcome: receipt.outcome,
+        shares: receipt.shares,
+        refund: receipt.collateral,
+    });
+
+    Ok(to_json(&SellResponse {
+        prices: format.prices(&receipt.price_after),
+        receipt: ReceiptView::new(&receipt, format),
+    }))
+}
+
+fn deposit(market: &Arc<Mutex<Market>>, deposit: DepositRequest, context: &Context, format: Format) -> Result<Value, ApiError> {
+    let amount = parse_amount("amount", &deposit.amount)?;
+
+    let mut market = lock(context, market)?;
+    market.engine.deposit(&deposit.trader, amount)?;
+    market.record(Entry::Deposit {
+        trader: deposit.trader.clone(),
+        amount,
+    });
+
+    Ok(to_json(&DepositResponse {
+        balance: format.amount(market.engine.balance(&deposit.trader)),
+        trader: deposit.trader,
+    }))
+}
+
+fn resolve(market: &Arc<Mutex<Market>>, resolve: ResolveRequest, context: &Context, format: Format) -> Result<Value, ApiError> {
+    let mut market = lock(context, market)?;
+    let entry = if resolve.outcome.eq_ignore_ascii_case("INVALID") {
+        Entry::Invalidate
+    } else {
+        Entry::Resolve { outcome: outcome_index(&market, &resolve.outcome)? }
+    };
+    let paid = match entry {
+        Entry::Resolve { outcome } => market.engine.resolve(outcome)?,
+        _ => market.engine.invalidate()?,
+    };
+    market.record(entry);
+
+    Ok(to_json(&ResolveResponse {
+        status: StatusView::new(market.engine.status),
+        paid: format.amount(paid),
+    }))
+}
+
+// Takes collected fees out of the market, all of them unless an amount is
+// given.
+fn withdraw_fees(
+    market: &Arc<Mutex<Market>>,
+    withdraw: WithdrawFeesRequest,
+    context: &Context,
+    format: Format,
+) -> Result<Value, ApiError> {
+    let amount = parse_limit("amount", &withdraw.amount)?;
+
+    let mut market = lock(context, market)?;
+    let amount = amount.unwrap_or(market.engine.fees_collected);
+    market.engine.withdraw_fees(amount)?;
+    market.record(Entry::WithdrawFees { amount });
+
+    Ok(to_json(&WithdrawFeesResponse {
+        withdrawn: format.amount(amount),
+        fees_collected: format.amount(market.engine.fees_collected),
+    }))
+}
+
+fn simulate(market: &Arc<Mutex<Market>>, request: BuyRequest, context: &Context, format: Format) -> Result<Value, ApiError> {
+    let side = trade_side(&request)?;
+    let size = match side {
+        Side::Buy => trade_size(&request)?,
+        Side::Sell => TradeSize::Shares(sell_size(&request)?),
+    };
+
+    let market = lock(context, market)?;
+    let outcome = outcome_index(&market, &request.outcome)?;
+
+    let shares = match size {
+        TradeSize::Shares(amount) => amount,
+        TradeSize::Collateral(budget) => market.engine.simulate_with_collateral(outcome, budget),
+    };
+    let quote = market.engine.quote(side, outcome, shares)?;
+    Ok(to_json(&QuoteResponse::new(&quote, format)))
+}
+
+// OHLC candles for `?interval=&from=&to=`. The range defaults to everything
+// up to now.
+fn history(market: &Arc<Mutex<Market>>, query: &str, context: &Context, format: Format) -> Result<Value, ApiError> {
+    let interval = parse_interval(query_param(query, "interval").unwrap_or("1m"))?;
+    let from = parse_time(query, "from")?.unwrap_or(0);
+    let to = parse_time(query, "to")?;
+
+    let market = lock(context, market)?;
+    // Include trades made in the current second
+    let to = to.unwrap_or(market.engine.now.saturating_add(1));
+    if from > to {
+        return Err(ApiError::bad_request("invalid_range", "from must not be after to")
+            .with_details(json!({ "from": from, "to": to })));
     }
-}
\ No newline at end of file
+
+    let candles = market.history.candles(interval, from, to);
+    Ok(to_json(&HistoryResponse {
+        outcomes: market.info.outcomes.clone(),
+        interval,
+        from,
+        to,
+        candles: candles.iter().map(|candle| CandleView::new(candle, format)).collect(),
+    }))
+}
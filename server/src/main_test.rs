@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use clap::Parser;
+    use lslmsr::journal::FsyncPolicy;
+    use tiny_http::{Header, Method, Request, TestRequest};
+
+    use super::super::config::{Args, Config};
+    use super::super::handle;
+    use super::super::ratelimit::{RateLimit, RateLimiter};
+    use super::super::registry::Registry;
+    use super::super::serve::{Context, Reply};
+
+    // SHA-256 of "secret" and "hunter2"
+    const SECRET: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+    const HUNTER2: &str = "f52fbd32b2b3b86ff88ef6c490628285f482af15ddcb29541f94bcf526a3f6c7";
+
+    struct Server {
+        dir: PathBuf,
+        config: Config,
+        registry: Registry,
+        limiter: RateLimiter,
+    }
+
+    impl Server {
+        // A server whose trader "alice" has key "secret" and whose admin
+        // "operator" has key "hunter2"
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("lslmsr-routes-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("lslmsr.toml");
+            let keys = format!(
+                "[[keys]]\ntrader = \"alice\"\nkey_sha256 = \"{}\"\n\n\
+                 [[keys]]\ntrader = \"operator\"\nrole = \"admin\"\nkey_sha256 = \"{}\"\n",
+                SECRET, HUNTER2
+            );
+            fs::write(&path, keys).unwrap();
+
+            let args = ["lslmsr-server", "--static-dir", "../client", "--config", path.to_str().unwrap()];
+            let config = Config::from_args(Args::parse_from(args)).unwrap();
+            let registry = Registry::open(dir.join("markets"), FsyncPolicy::Never).unwrap();
+            let limiter = RateLimiter::new(RateLimit { per_second: 0, burst: 1 });
+            Server { dir, config, registry, limiter }
+        }
+
+        // The status and error code (empty on success) of a request
+        fn send(&self, method: Method, path: &str, key: Option<&str>, body: &'static str) -> (u16, &'static str) {
+            let mut request = TestRequest::new().with_method(method).with_path(path).with_body(body);
+            if let Some(key) = key {
+                let header = format!("Bearer {}", key);
+                request = request.with_header(Header::from_bytes("Authorization", header).unwrap());
+            }
+            let mut request = Request::from(request);
+            let context = Context {
+                deadline: Instant::now() + Duration::from_secs(5),
+                max_body: 1024,
+            };
+            match handle(&self.config, &self.registry, &self.limiter, &mut request, &context) {
+                Ok(Reply::Response(response)) => (response.status_code().0, ""),
+                Ok(Reply::Stream(_)) => (200, ""),
+                Err(err) => (err.status, err.code),
+            }
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    const MARKET: &str = r#"{"question": "Will it rain?"}"#;
+    const DEPOSIT: &str = r#"{"trader": "alice", "amount": "100000000000000000000"}"#;
+    const BUY: &str = r#"{"outcome": "YES", "amount": "1000000000000000000"}"#;
+
+    #[test]
+    fn test_admin_routes_need_an_admin_key() {
+        let server = Server::new("admin");
+        assert_eq!(server.send(Method::Post, "/markets", None, MARKET), (401, "unauthorized"));
+        assert_eq!(server.send(Method::Post, "/markets", Some("wrong"), MARKET), (401, "unauthorized"));
+        assert_eq!(server.send(Method::Post, "/markets", Some("secret"), MARKET), (403, "forbidden"));
+        assert_eq!(server.send(Method::Post, "/markets", Some("hunter2"), MARKET), (201, ""));
+
+        assert_eq!(server.send(Method::Post, "/markets/1/deposit", Some("secret"), DEPOSIT), (403, "forbidden"));
+        assert_eq!(server.send(Method::Post, "/markets/1/close", Some("secret"), ""), (403, "forbidden"));
+        assert_eq!(server.send(Method::Post, "/markets/1/deposit", Some("hunter2"), DEPOSIT), (200, ""));
+    }
+
+    #[test]
+    fn test_trading_needs_a_key_and_prices_do_not() {
+        let server = Server::new("trading");
+        assert_eq!(server.send(Method::Post, "/markets", Some("hunter2"), MARKET), (201, ""));
+        assert_eq!(server.send(Method::Post, "/markets/1/deposit", Some("hunter2"), DEPOSIT), (200, ""));
+
+        assert_eq!(server.send(Method::Post, "/markets/1/buy", None, BUY), (401, "unauthorized"));
+        assert_eq!(server.send(Method::Post, "/markets/1/buy", Some("secret"), BUY), (200, ""));
+
+        // A trader key trades only for its own trader
+        let bob = r#"{"trader": "bob", "outcome": "YES", "amount": "1000000000000000000"}"#;
+        assert_eq!(server.send(Method::Post, "/markets/1/buy", Some("secret"), bob), (403, "trader_mismatch"));
+
+        assert_eq!(server.send(Method::Get, "/markets", None, ""), (200, ""));
+        assert_eq!(server.send(Method::Get, "/markets/1/price", None, ""), (200, ""));
+        assert_eq!(server.send(Method::Get, "/markets/1/history", None, ""), (200, ""));
+    }
+
+    #[test]
+    fn test_unknown_routes() {
+        let server = Server::new("unknown");
+        assert_eq!(server.send(Method::Get, "/markets/7/price", None, ""), (404, "market_not_found"));
+        assert_eq!(server.send(Method::Get, "/nowhere", None, ""), (404, "not_found"));
+        assert_eq!(server.send(Method::Post, "/markets", Some("hunter2"), MARKET), (201, ""));
+        assert_eq!(server.send(Method::Get, "/markets/1/nowhere", None, ""), (404, "not_found"));
+    }
+}
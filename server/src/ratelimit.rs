@@ -0,0 +1,83 @@
+// Per-client request rate limiting.
+//
+// Each client has a token bucket holding up to `burst` requests and refilled
+// at `per_second`. Every request spends a token; with none left it is refused
+// with 429 until the bucket refills. Clients are told apart by the trader of
+// a valid API key, or else by IP address.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde_json::json;
+
+use crate::error::ApiError;
+
+// Most clients tracked at once.
+pub const MAX_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Steady requests per second; 0 turns limiting off.
+    pub per_second: u32,
+    /// Requests a client may make at once.
+    pub burst: u32,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Spends one of `client`'s requests, failing with 429 if none are left.
+    pub fn check(&self, client: &str) -> Result<(), ApiError> {
+        self.check_at(client, Instant::now())
+    }
+
+    /// `check` as of `now`. A time before a client's last request counts as
+    /// no time passing.
+    pub fn check_at(&self, client: &str, now: Instant) -> Result<(), ApiError> {
+        if self.limit.per_second == 0 {
+            return Ok(());
+        }
+        let rate = f64::from(self.limit.per_second);
+        let burst = f64::from(self.limit.burst);
+        let refilled = |bucket: &Bucket| bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(client) {
+            // Forget the quarter of clients heard from least recently, whose
+            // buckets have most likely refilled anyway. Evicting in bulk
+            // keeps the cost per new client low.
+            let mut updated: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+            let (_, &mut cutoff, _) = updated.select_nth_unstable(MAX_CLIENTS / 4);
+            buckets.retain(|_, bucket| bucket.updated > cutoff);
+        }
+
+        let bucket = buckets
+            .entry(client.to_string())
+            .or_insert(Bucket { tokens: burst, updated: now });
+        bucket.tokens = refilled(bucket).min(burst);
+        bucket.updated = bucket.updated.max(now);
+        if bucket.tokens < 1.0 {
+            let retry_after_ms = ((1.0 - bucket.tokens) / rate * 1000.0).ceil() as u64;
+            return Err(ApiError::new(429, "rate_limited", "Too many requests")
+                .with_details(json!({ "retry_after_ms": retry_after_ms }))
+                .with_retry_after(retry_after_ms.div_ceil(1000)));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
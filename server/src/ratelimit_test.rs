@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::super::ratelimit::*;
+
+    fn limiter(per_second: u32, burst: u32) -> RateLimiter {
+        RateLimiter::new(RateLimit { per_second, burst })
+    }
+
+    // How long a refused request was told to wait
+    fn retry_after_ms(limiter: &RateLimiter, client: &str, now: Instant) -> u64 {
+        let err = limiter.check_at(client, now).unwrap_err();
+        assert_eq!((err.status, err.code), (429, "rate_limited"));
+        let retry_after_ms = err.details["retry_after_ms"].as_u64().unwrap();
+        assert_eq!(err.retry_after, Some(retry_after_ms.div_ceil(1000)));
+        retry_after_ms
+    }
+
+    #[test]
+    fn test_burst() {
+        let limiter = limiter(2, 3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.check_at("alice", start).unwrap();
+        }
+        assert_eq!(retry_after_ms(&limiter, "alice", start), 500);
+    }
+
+    #[test]
+    fn test_refill() {
+        let limiter = limiter(2, 3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.check_at("alice", start).unwrap();
+        }
+
+        // Half a token is not enough
+        let later = start + Duration::from_millis(250);
+        assert_eq!(retry_after_ms(&limiter, "alice", later), 250);
+        let later = start + Duration::from_millis(500);
+        limiter.check_at("alice", later).unwrap();
+        assert_eq!(retry_after_ms(&limiter, "alice", later), 500);
+
+        // A long pause refills only up to the burst
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            limiter.check_at("alice", later).unwrap();
+        }
+        assert!(limiter.check_at("alice", later).is_err());
+
+        // A clock that goes backwards refills nothing
+        assert!(limiter.check_at("alice", start).is_err());
+    }
+
+    #[test]
+    fn test_no_limit() {
+        let limiter = limiter(0, 1);
+        let start = Instant::now();
+        for _ in 0..1_000 {
+            limiter.check_at("alice", start).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_clients_are_separate() {
+        let limiter = limiter(1, 2);
+        let start = Instant::now();
+        limiter.check_at("alice", start).unwrap();
+        limiter.check_at("alice", start).unwrap();
+        assert!(limiter.check_at("alice", start).is_err());
+
+        // Alice running out leaves Bob's bucket full
+        limiter.check_at("bob", start).unwrap();
+        limiter.check_at("bob", start).unwrap();
+        assert!(limiter.check_at("bob", start).is_err());
+        assert!(limiter.check_at("alice", start).is_err());
+    }
+
+    #[test]
+    fn test_least_recent_clients_are_forgotten() {
+        let limiter = limiter(1, 1);
+        let start = Instant::now();
+        let at = |i: usize| start + Duration::from_micros(i as u64);
+        for i in 0..MAX_CLIENTS {
+            limiter.check_at(&i.to_string(), at(i)).unwrap();
+        }
+
+        // A new client past the cap evicts the oldest quarter, even though
+        // none of their buckets have refilled, and the rest stay limited
+        let now = at(MAX_CLIENTS);
+        limiter.check_at("new", now).unwrap();
+        limiter.check_at("0", now).unwrap();
+        limiter.check_at(&(MAX_CLIENTS / 4).to_string(), now).unwrap();
+        assert!(limiter.check_at(&(MAX_CLIENTS / 4 + 1).to_string(), now).is_err());
+        assert!(limiter.check_at(&(MAX_CLIENTS - 1).to_string(), now).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+// Every market the server hosts, keyed by ID.
+//
+// Each market lives in the data directory as two files: `<id>.json` holds
+// what it was created with and `<id>.journal` every operation since. On
+// startup the registry reloads each market by replaying its journal, which
+// also rebuilds its price history. Operations recorded after that are
+// published to the registry's `Events` for live streams.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lslmsr::fees::FeeSchedule;
+use lslmsr::history::PriceHistory;
+use lslmsr::journal::{self, Entry, FsyncPolicy, Journal, JournalError};
+use lslmsr::market::MarketEngine;
+use lslmsr::types::{Side, TradeError, TradeLimits};
+use serde::{Deserialize, Serialize};
+
+use crate::events::{Event, Events};
+
+pub type MarketId = u64;
+
+/// What a market was created with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketInfo {
+    pub id: MarketId,
+    pub question: String,
+    /// Outcome names, in index order.
+    pub outcomes: Vec<String>,
+    pub alpha: u128,
+    /// Unix time in seconds after which trading stops.
+    pub close_time: Option<u64>,
+    /// Most shares per trade, if bounded.
+    pub max_trade: Option<u128>,
+    /// Most shares of one outcome per trader, if bounded.
+    pub max_position: Option<u128>,
+    /// Markets created before fees existed charge none.
+    #[serde(default)]
+    pub fees: FeeSchedule,
+    /// Collateral the operator put up to cover the market maker's loss.
+    #[serde(default)]
+    pub subsidy: u128,
+}
+
+pub struct Market {
+    pub info: MarketInfo,
+    pub engine: MarketEngine,
+    /// Prices after every trade, for charts.
+    pub history: PriceHistory,
+    journal: Journal,
+    events: Arc<Events>,
+    // The clock as of the last journaled operation
+    journaled_now: u64,
+    // Where the journal lives, to reload it
+    dir: PathBuf,
+    fsync: FsyncPolicy,
+}
+
+impl Market {
+    /// Looks up an outcome by name (case-insensitive) or index.
+    pub fn outcome_index(&self, outcome: &str) -> Option<usize> {
+        self.info
+            .outcomes
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(outcome))
+            .or_else(|| outcome.parse().ok().filter(|&index| index < self.info.outcomes.len()))
+    }
+
+    /// Rebuilds the market from its journal, discarding anything a request
+    /// that panicked part way through left behind. Only journaled
+    /// operations survive, and those are the only ones acknowledged.
+    pub fn reload(&mut self) -> Result<(), JournalError> {
+        *self = load(&self.dir, self.info.clone(), self.fsync, &self.events)?;
+        Ok(())
+    }
+
+    /// Moves the market clock to the current wall-clock time.
+    pub fn tick(&mut self) {
+        self.engine.set_time(unix_now());
+    }
+
+    /// Journals an operation the engine has accepted, preceded by the clock
+    /// if it moved since the last one, adds trades to the price history and
+    /// publishes the change to live streams.
+    ///
+    /// Once the engine has accepted a change it must reach the journal, or
+    /// a restart would silently lose it, so this panics rather than let the
+    /// caller acknowledge it.
+    pub fn record(&mut self, entry: Entry) {
+        if self.engine.now != self.journaled_now {
+            self.journaled_now = self.engine.now;
+            let clock = Entry::Clock { now: self.engine.now };
+            self.journal.append(&clock).expect("failed to write trade journal");
+        }
+        self.journal.append(&entry).expect("failed to write trade journal");
+        track(&mut self.history, &self.engine, &entry);
+        self.publish(entry);
+    }
+
+    /// The event a stream starts from: the current prices.
+    pub fn snapshot(&self) -> Result<Event, TradeError> {
+        Ok(Event::Price {
+            market: self.info.id,
+            sequence: self.engine.sequence,
+            prices: self.engine.get_price()?,
+        })
+    }
+
+    fn publish(&self, entry: Entry) {
+        // Streams are public, so trade events leave out who traded
+        let (side, outcome, shares, collateral) = match entry {
+            Entry::Buy { outcome, shares, cost, .. } => (Side::Buy, outcome, shares, cost),
+            Entry::Sell { outcome, shares, refund, .. } => (Side::Sell, outcome, shares, refund),
+            Entry::Close | Entry::Resolve { .. } | Entry::Invalidate => {
+                self.events.publish(Event::Status {
+                    market: self.info.id,
+                    status: self.engine.status,
+                });
+                return;
+            }
+            Entry::Clock { .. }
+            | Entry::Deposit { .. }
+            | Entry::Withdraw { .. }
+            | Entry::WithdrawFees { .. } => return,
+        };
+        // The engine priced this state to accept the trade, so this only
+        // fails if the liquidity has since changed under it
+        let Ok(prices) = self.engine.get_price() else { return };
+        self.events.publish(Event::Trade {
+            market: self.info.id,
+            sequence: self.engine.sequence,
+            time: self.engine.now,
+            side,
+            outcome,
+            shares,
+            collateral,
+            prices,
+        });
+    }
+
+    /// Forces this market's journal to disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.journal.sync()
+    }
+}
+
+pub struct Registry {
+    dir: PathBuf,
+    fsync: FsyncPolicy,
+    events: Arc<Events>,
+    markets: RwLock<BTreeMap<MarketId, Arc<Mutex<Market>>>>,
+}
+
+impl Registry {
+    /// Loads every market in `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>, fsync: FsyncPolicy) -> Result<Self, JournalError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let events = Arc::new(Events::default());
+        let mut markets = BTreeMap::new();
+        for file in fs::read_dir(&dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let info: MarketInfo = serde_json::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let market = load(&dir, info, fsync, &events)?;
+                markets.insert(market.info.id, Arc::new(Mutex::new(market)));
+            }
+        }
+
+        Ok(Registry {
+            dir,
+            fsync,
+            events,
+            markets: RwLock::new(markets),
+        })
+    }
+
+    /// Where every market publishes its trades and status changes.
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    pub fn get(&self, id: MarketId) -> Option<Arc<Mutex<Market>>> {
+        self.markets.read().unwrap().get(&id).cloned()
+    }
+
+    /// Every market, in ID order.
+    pub fn list(&self) -> Vec<Arc<Mutex<Market>>> {
+        self.markets.read().unwrap().values().cloned().collect()
+    }
+
+    /// Forces every market's journal to disk, e.g. before shutting down.
+    pub fn sync(&self) -> io::Result<()> {
+        for market in self.list() {
+            // A poisoned market's journal still holds only accepted records
+            market.lock().unwrap_or_else(PoisonError::into_inner).sync()?;
+        }
+        Ok(())
+    }
+
+    /// Creates and persists a new market. `info.id` is ignored; the new
+    /// market gets the next free ID.
+    pub fn create(&self, mut info: MarketInfo) -> Result<Arc<Mutex<Market>>, JournalError> {
+        let mut markets = self.markets.write().unwrap();
+        info.id = markets.keys().next_back().map_or(1, |id| id + 1);
+
+        // Write the metadata under a temporary name first, so a crash never
+        // leaves a half-written market behind
+        let path = self.dir.join(format!("{}.json", info.id));
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(&info).expect("market info always serializes"))?;
+        fs::rename(&temp, &path)?;
+
+        let market = Arc::new(Mutex::new(load(&self.dir, info, self.fsync, &self.events)?));
+        markets.insert(market.lock().unwrap().info.id, Arc::clone(&market));
+        Ok(market)
+    }
+}
+
+// Builds a market from its metadata and replays its journal.
+fn load(dir: &Path, info: MarketInfo, fsync: FsyncPolicy, events: &Arc<Events>) -> Result<Market, JournalError> {
+    let mut engine = MarketEngine::with_outcomes(info.alpha, info.outcomes.len());
+    engine.close_time = info.close_time;
+    engine.limits = TradeLimits {
+        max_trade: info.max_trade,
+        max_position: info.max_position,
+    };
+    engine.fees = info.fees.clone();
+    engine.subsidy = info.subsidy;
+
+    let prices = engine.get_price().expect("an empty market has uniform prices");
+    let mut history = PriceHistory::new(&prices);
+    let (journal, entries) = Journal::open(dir.join(format!("{}.journal", info.id)), fsync)?;
+    journal::replay_with(&mut engine, &entries, |engine, entry| track(&mut history, engine, entry))?;
+    log::info!("Market {}: replayed {} journal records", info.id, entries.len());
+
+    Ok(Market {
+        info,
+        journaled_now: engine.now,
+        engine,
+        history,
+        journal,
+        events: Arc::clone(events),
+        dir: dir.to_path_buf(),
+        fsync,
+    })
+}
+
+// Adds a trade the engine has just applied to the price history.
+fn track(history: &mut PriceHistory, engine: &MarketEngine, entry: &Entry) {
+    if let Entry::Buy { cost: volume, .. } | Entry::Sell { refund: volume, .. } = entry {
+        if let Ok(prices) = engine.get_price() {
+            history.record(engine.now, &prices, *volume);
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use lslmsr::fees::FeeSchedule;
+    use lslmsr::journal::{Entry, FsyncPolicy};
+    use lslmsr::types::{Outcome, DECIMALS};
+
+    use super::super::events;
+    use super::super::lock;
+    use super::super::registry::*;
+    use super::super::schema::Format;
+    use super::super::serve::Context;
+
+    // A fresh data directory per test, so tests can run in parallel
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lslmsr-server-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn info() -> MarketInfo {
+        MarketInfo {
+            id: 0,
+            question: "Will it rain?".to_string(),
+            outcomes: vec!["YES".to_string(), "NO".to_string()],
+            alpha: DECIMALS,
+            close_time: None,
+            max_trade: None,
+            max_position: None,
+            fees: FeeSchedule::None,
+            subsidy: 0,
+        }
+    }
+
+    fn context() -> Context {
+        Context {
+            deadline: Instant::now() + Duration::from_secs(5),
+            max_body: 1024,
+        }
+    }
+
+    #[test]
+    fn test_poisoned_market_reloads_its_journal() {
+        let dir = data_dir("poisoned");
+        let registry = Registry::open(&dir, FsyncPolicy::Never).unwrap();
+        let market = registry.create(info()).unwrap();
+        {
+            let mut market = market.lock().unwrap();
+            market.engine.deposit("alice", 100 * DECIMALS).unwrap();
+            market.record(Entry::Deposit { trader: "alice".to_string(), amount: 100 * DECIMALS });
+        }
+
+        // A request panics after changing the engine but before journaling
+        let poisoner = Arc::clone(&market);
+        let result = thread::spawn(move || {
+            let mut market = poisoner.lock().unwrap();
+            market.engine.deposit("alice", 50 * DECIMALS).unwrap();
+            panic!("request failed part way through");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(market.is_poisoned());
+
+        // The next request sees only what was journaled, and so do later ones
+        let recovered = lock(&context(), &market).unwrap();
+        assert_eq!(recovered.engine.balance("alice"), 100 * DECIMALS);
+        assert_eq!(recovered.engine.get_price().unwrap().outcomes, vec![DECIMALS / 2; 2]);
+        drop(recovered);
+        assert!(!market.is_poisoned());
+        assert_eq!(lock(&context(), &market).unwrap().engine.balance("alice"), 100 * DECIMALS);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_trade_events_leave_out_the_trader() {
+        let dir = data_dir("anonymous");
+        let registry = Registry::open(&dir, FsyncPolicy::Never).unwrap();
+        let market = registry.create(info()).unwrap();
+        let id = market.lock().unwrap().info.id;
+        let subscription = registry.events().subscribe(Some(id)).unwrap();
+        {
+            let mut market = market.lock().unwrap();
+            market.engine.deposit("alice", 100 * DECIMALS).unwrap();
+            market.record(Entry::Deposit { trader: "alice".to_string(), amount: 100 * DECIMALS });
+            let receipt = market.engine.buy("alice", Outcome::Yes, DECIMALS, None).unwrap();
+            market.record(Entry::Buy {
+                trader: receipt.trader,
+                outcome: receipt.outcome,
+                shares: receipt.shares,
+                cost: receipt.collateral,
+            });
+        }
+
+        // The stream ends once nothing is left to publish to it
+        drop(market);
+        drop(registry);
+        let path = dir.join("stream");
+        events::stream(Box::new(fs::File::create(&path).unwrap()), vec![], subscription, Format::Fixed);
+        let output = fs::read_to_string(&path).unwrap();
+        assert!(output.contains("event: trade\n"));
+        assert!(!output.contains("alice"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
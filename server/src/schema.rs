@@ -0,0 +1,389 @@
+// Response bodies for every route.
+//
+// Fixed-point amounts (prices, shares, collateral, alpha, b) are always
+// strings, so they survive JSON parsers that read numbers as f64. By default
+// they are the raw 1e18-scaled integer; with `?format=decimal` they are
+// exact decimal strings instead ("0.54" rather than "540000000000000000").
+// Either way the shape of each body is the same.
+use lslmsr::fees::FeeSchedule;
+use lslmsr::fixed::to_decimal_string;
+use lslmsr::history::Candle;
+use lslmsr::types::{MarketStatus, Price, Quote, Side, Solvency, TradeReceipt};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::error::ApiError;
+use crate::registry::{Market, MarketId};
+
+/// How fixed-point amounts are written in a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The 1e18-scaled integer, e.g. "540000000000000000".
+    Fixed,
+    /// An exact decimal, e.g. "0.54".
+    Decimal,
+}
+
+impl Format {
+    /// Reads the `format` query parameter, defaulting to `Fixed`.
+    pub fn from_query(query: &str) -> Result<Format, ApiError> {
+        match query_param(query, "format") {
+            None | Some("fixed") => Ok(Format::Fixed),
+            Some("decimal") => Ok(Format::Decimal),
+            Some(other) => Err(ApiError::bad_request(
+                "invalid_format",
+                "Format must be \"fixed\" or \"decimal\"",
+            )
+            .with_details(json!({ "format": other }))),
+        }
+    }
+
+    pub fn amount(self, value: u128) -> String {
+        match self {
+            Format::Fixed => value.to_string(),
+            Format::Decimal => to_decimal_string(value),
+        }
+    }
+
+    /// A price vector, indexed by outcome.
+    pub fn prices(self, price: &Price) -> Vec<String> {
+        price.outcomes.iter().map(|&value| self.amount(value)).collect()
+    }
+}
+
+/// The value of `key` in a URL query string, if present.
+pub fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+}
+
+/// Serializes a response body.
+pub fn to_json(body: &impl Serialize) -> Value {
+    serde_json::to_value(body).expect("response bodies always serialize")
+}
+
+pub fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+#[derive(Serialize)]
+pub struct MarketView {
+    pub id: MarketId,
+    pub question: String,
+    pub outcomes: Vec<String>,
+    pub alpha: String,
+    pub close_time: Option<u64>,
+    pub max_trade: Option<String>,
+    pub max_position: Option<String>,
+    pub fees: FeeView,
+    /// Fees charged and not yet withdrawn.
+    pub fees_collected: String,
+    pub subsidy: String,
+    #[serde(flatten)]
+    pub status: StatusView,
+    pub prices: Vec<String>,
+}
+
+impl MarketView {
+    pub fn new(market: &Market, format: Format) -> Result<Self, ApiError> {
+        Ok(MarketView {
+            id: market.info.id,
+            question: market.info.question.clone(),
+            outcomes: market.info.outcomes.clone(),
+            alpha: format.amount(market.info.alpha),
+            close_time: market.info.close_time,
+            max_trade: market.info.max_trade.map(|max| format.amount(max)),
+            max_position: market.info.max_position.map(|max| format.amount(max)),
+            fees: FeeView::new(&market.engine.fees, format),
+            fees_collected: format.amount(market.engine.fees_collected),
+            subsidy: format.amount(market.engine.subsidy),
+            status: StatusView::new(market.engine.status),
+            prices: format.prices(&market.engine.get_price()?),
+        })
+    }
+}
+
+/// A market's fee schedule, shaped like the `fees` of a create request.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeeView {
+    None,
+    Flat { bps: u32 },
+    PriceDependent { bps: u32 },
+    Tiered { tiers: Vec<FeeTierView> },
+}
+
+#[derive(Serialize)]
+pub struct FeeTierView {
+    pub min_volume: String,
+    pub bps: u32,
+}
+
+impl FeeView {
+    pub fn new(fees: &FeeSchedule, format: Format) -> Self {
+        match fees {
+            FeeSchedule::None => FeeView::None,
+            FeeSchedule::Flat { bps } => FeeView::Flat { bps: *bps },
+            FeeSchedule::PriceDependent { bps } => FeeView::PriceDependent { bps: *bps },
+            FeeSchedule::Tiered { tiers } => FeeView::Tiered {
+                tiers: tiers
+                    .iter()
+                    .map(|tier| FeeTierView {
+                        min_volume: format.amount(tier.min_volume),
+                        bps: tier.bps,
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MarketList {
+    pub markets: Vec<MarketView>,
+}
+
+#[derive(Serialize)]
+pub struct PriceResponse {
+    pub outcomes: Vec<String>,
+    pub prices: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReceiptView {
+    pub sequence: u64,
+    pub trader: String,
+    pub side: &'static str,
+    pub outcome: usize,
+    pub shares: String,
+    /// Paid for a buy, refunded for a sell, before the fee.
+    pub collateral: String,
+    /// Charged on top of a buy's collateral, or out of a sell's.
+    pub fee: String,
+    pub average_price: String,
+    pub price_before: Vec<String>,
+    pub price_after: Vec<String>,
+    pub b_before: String,
+    pub b_after: String,
+}
+
+impl ReceiptView {
+    pub fn new(receipt: &TradeReceipt, format: Format) -> Self {
+        ReceiptView {
+            sequence: receipt.sequence,
+            trader: receipt.trader.clone(),
+            side: side_name(receipt.side),
+            outcome: receipt.outcome,
+            shares: format.amount(receipt.shares),
+            collateral: format.amount(receipt.collateral),
+            fee: format.amount(receipt.fee),
+            average_price: format.amount(receipt.average_price),
+            price_before: format.prices(&receipt.price_before),
+            price_after: format.prices(&receipt.price_after),
+            b_before: format.amount(receipt.b_before),
+            b_after: format.amount(receipt.b_after),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BuyResponse {
+    pub shares: String,
+    pub prices: Vec<String>,
+    pub receipt: ReceiptView,
+}
+
+#[derive(Serialize)]
+pub struct SellResponse {
+    pub prices: Vec<String>,
+    pub receipt: ReceiptView,
+}
+
+#[derive(Serialize)]
+pub struct QuoteResponse {
+    pub side: &'static str,
+    pub simulated_shares: String,
+    /// Set for buys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulated_cost: Option<String>,
+    /// Set for sells.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulated_refund: Option<String>,
+    /// On top of the cost, or out of the refund, for a trader with no
+    /// volume yet.
+    pub fee: String,
+    pub average_price: String,
+    pub price_after: Vec<String>,
+}
+
+impl QuoteResponse {
+    pub fn new(quote: &Quote, format: Format) -> Self {
+        let collateral = format.amount(quote.collateral);
+        let (simulated_cost, simulated_refund) = match quote.side {
+            Side::Buy => (Some(collateral), None),
+            Side::Sell => (None, Some(collateral)),
+        };
+        QuoteResponse {
+            side: side_name(quote.side),
+            simulated_shares: format.amount(quote.shares),
+            simulated_cost,
+            simulated_refund,
+            fee: format.amount(quote.fee),
+            average_price: format.amount(quote.average_price),
+            price_after: format.prices(&quote.price_after),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DepositResponse {
+    pub trader: String,
+    pub balance: String,
+}
+
+/// A market's lifecycle status, `"open"`, `"closed"`, `"resolved"` or
+/// `"invalid"`, with the index of the winning outcome once resolved.
+#[derive(Serialize)]
+pub struct StatusView {
+    pub status: &'static str,
+    pub winning_outcome: Option<usize>,
+}
+
+impl StatusView {
+    pub fn new(status: MarketStatus) -> Self {
+        let (status, winning_outcome) = match status {
+            MarketStatus::Open => ("open", None),
+            MarketStatus::Closed => ("closed", None),
+            MarketStatus::Resolved(outcome) => ("resolved", Some(outcome)),
+            MarketStatus::Invalid => ("invalid", None),
+        };
+        StatusView { status, winning_outcome }
+    }
+}
+
+#[derive(Serialize)]
+pub struct StatusResponse {
+    #[serde(flatten)]
+    pub status: StatusView,
+}
+
+#[derive(Serialize)]
+pub struct ResolveResponse {
+    #[serde(flatten)]
+    pub status: StatusView,
+    /// Total collateral paid out to traders.
+    pub paid: String,
+}
+
+#[derive(Serialize)]
+pub struct WithdrawFeesResponse {
+    pub withdrawn: String,
+    /// Fees left in the market.
+    pub fees_collected: String,
+}
+
+#[derive(Serialize)]
+pub struct SolvencyResponse {
+    pub subsidy: String,
+    /// Collateral traders have paid in for their shares.
+    pub total_collateral: String,
+    /// Most the market could owe if settled now, or what it paid once settled.
+    pub max_payout: String,
+    /// What the operator would lose if the most-held outcome won now, or
+    /// what it lost once settled.
+    pub current_loss: String,
+    /// Bound on the operator's loss over the rest of the market's life.
+    pub max_loss: String,
+    /// Whether the subsidy covers `max_loss`.
+    pub fully_funded: bool,
+}
+
+impl SolvencyResponse {
+    pub fn new(solvency: &Solvency, format: Format) -> Self {
+        SolvencyResponse {
+            subsidy: format.amount(solvency.subsidy),
+            total_collateral: format.amount(solvency.total_collateral),
+            max_payout: format.amount(solvency.max_payout),
+            current_loss: format.amount(solvency.current_loss),
+            max_loss: format.amount(solvency.max_loss),
+            fully_funded: solvency.subsidy >= solvency.max_loss,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CandleView {
+    /// Unix time in seconds at which the candle starts.
+    pub start: u64,
+    pub open: Vec<String>,
+    pub high: Vec<String>,
+    pub low: Vec<String>,
+    pub close: Vec<String>,
+    /// Collateral traded during the candle.
+    pub volume: String,
+    pub trades: u32,
+}
+
+impl CandleView {
+    pub fn new(candle: &Candle, format: Format) -> Self {
+        let prices = |prices: &[u128]| prices.iter().map(|&price| format.amount(price)).collect();
+        CandleView {
+            start: candle.start,
+            open: prices(&candle.open),
+            high: prices(&candle.high),
+            low: prices(&candle.low),
+            close: prices(&candle.close),
+            volume: format.amount(candle.volume),
+            trades: candle.trades,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    pub outcomes: Vec<String>,
+    /// Candle length in seconds.
+    pub interval: u64,
+    pub from: u64,
+    pub to: u64,
+    pub candles: Vec<CandleView>,
+}
+
+/// `data` of a `price` event on `/stream`.
+#[derive(Serialize)]
+pub struct PriceEvent {
+    pub market: MarketId,
+    /// Trades so far; later events have higher sequences.
+    pub sequence: u64,
+    pub prices: Vec<String>,
+}
+
+/// `data` of a `trade` event on `/stream`.
+#[derive(Serialize)]
+pub struct TradeEvent {
+    pub market: MarketId,
+    pub sequence: u64,
+    /// Market clock, in seconds.
+    pub time: u64,
+    pub side: &'static str,
+    pub outcome: usize,
+    pub shares: String,
+    /// Paid for a buy, refunded for a sell.
+    pub collateral: String,
+    /// Prices after the trade.
+    pub prices: Vec<String>,
+}
+
+/// `data` of a `status` event on `/stream`.
+#[derive(Serialize)]
+pub struct StatusEvent {
+    pub market: MarketId,
+    #[serde(flatten)]
+    pub status: StatusView,
+}
@@ -0,0 +1,200 @@
+// Runs the request handler on a pool of worker threads.
+//
+// tiny_http parses requests on its own connection threads and queues them;
+// each worker takes the next one, handles it and responds, so a slow
+// request only holds up its own worker. Workers poll a shutdown flag set by
+// SIGTERM or SIGINT, finish the request in hand and exit.
+//
+// tiny_http does not expose socket timeouts, so the request timeout is
+// enforced where the handler waits: reading the body and waiting for a
+// market's lock. Bodies of up to 1 KiB are read by tiny_http before the
+// request is queued.
+//
+// A handler may instead take over the connection, for a response that
+// streams for as long as the client stays. That runs on a thread of its own,
+// so it does not hold up a worker, and is not waited for on shutdown.
+use std::io::{self, Cursor, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tiny_http::{Request, Response, Server};
+
+use crate::error::ApiError;
+
+pub type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+/// What a handler answers a request with.
+pub enum Reply {
+    Response(HttpResponse),
+    /// Writes the raw HTTP response, status line and headers included, to
+    /// the connection.
+    Stream(Box<dyn FnOnce(Box<dyn Write + Send>) + Send>),
+}
+
+impl From<HttpResponse> for Reply {
+    fn from(response: HttpResponse) -> Self {
+        Reply::Response(response)
+    }
+}
+
+// How long an idle worker waits for a request before checking for shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub workers: usize,
+    /// How long a request may take to read and to wait for a market.
+    pub request_timeout: Duration,
+    /// The largest request body accepted, in bytes.
+    pub max_body: usize,
+}
+
+/// Per-request limits passed to the handler.
+pub struct Context {
+    pub deadline: Instant,
+    pub max_body: usize,
+}
+
+impl Context {
+    /// Reads the whole request body as UTF-8, within the size and time limits.
+    pub fn read_body(&self, request: &mut Request) -> Result<String, ApiError> {
+        if request.body_length().is_some_and(|length| length > self.max_body) {
+            return Err(payload_too_large(self.max_body));
+        }
+
+        // Chunked bodies have no declared length, so cap the read as well
+        let mut reader = DeadlineReader {
+            inner: request.as_reader().take(self.max_body as u64 + 1),
+            deadline: self.deadline,
+        };
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).map_err(|err| match err.kind() {
+            io::ErrorKind::TimedOut => ApiError::new(408, "request_timeout", "Timed out reading the request body"),
+            _ => ApiError::bad_request("invalid_body", "Failed to read the request body"),
+        })?;
+        if body.len() > self.max_body {
+            return Err(payload_too_large(self.max_body));
+        }
+
+        String::from_utf8(body).map_err(|_| ApiError::bad_request("invalid_body", "Request body must be UTF-8"))
+    }
+
+    /// Locks `mutex`, giving up at the deadline. A mutex poisoned by a
+    /// panicking request may guard a half-updated value, so `recover` must
+    /// rebuild it before it is used again; until it succeeds the value stays
+    /// unavailable.
+    pub fn lock<'a, T>(
+        &self,
+        mutex: &'a Mutex<T>,
+        recover: impl Fn(&mut T) -> bool,
+    ) -> Result<MutexGuard<'a, T>, ApiError> {
+        loop {
+            match mutex.try_lock() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::Poisoned(poisoned)) => {
+                    let mut guard = poisoned.into_inner();
+                    if !recover(&mut guard) {
+                        return Err(ApiError::internal("Unavailable after an internal error"));
+                    }
+                    mutex.clear_poison();
+                    return Ok(guard);
+                }
+                Err(TryLockError::WouldBlock) if Instant::now() >= self.deadline => {
+                    return Err(ApiError::new(503, "busy", "Timed out waiting for the market"))
+                }
+                Err(TryLockError::WouldBlock) => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+    }
+}
+
+fn payload_too_large(max_body: usize) -> ApiError {
+    ApiError::new(413, "payload_too_large", "Request body is too large")
+        .with_details(serde_json::json!({ "max_bytes": max_body }))
+}
+
+// Fails reads once the deadline has passed. A read already blocked on the
+// socket is not interrupted, but a client trickling its body is cut off.
+struct DeadlineReader<R> {
+    inner: R,
+    deadline: Instant,
+}
+
+impl<R: Read> Read for DeadlineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if Instant::now() >= self.deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// Sets the returned flag on SIGTERM or SIGINT.
+pub fn shutdown_flag() -> io::Result<Arc<AtomicBool>> {
+    let flag = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&flag))?;
+    }
+    Ok(flag)
+}
+
+/// Serves requests until `shutdown` is set, then waits for every worker to
+/// finish the request it is handling.
+pub fn run<F>(server: &Server, options: &Options, shutdown: &AtomicBool, handler: F)
+where
+    F: Fn(&mut Request, &Context) -> Result<Reply, ApiError> + Sync,
+{
+    thread::scope(|scope| {
+        for _ in 0..options.workers.max(1) {
+            scope.spawn(|| {
+                while !shutdown.load(Ordering::Relaxed) {
+                    match server.recv_timeout(POLL_INTERVAL) {
+                        Ok(Some(request)) => serve(request, options, &handler),
+                        Ok(None) => {}
+                        Err(err) => {
+                            log::error!("failed to receive request: {}", err);
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn serve<F>(mut request: Request, options: &Options, handler: &F)
+where
+    F: Fn(&mut Request, &Context) -> Result<Reply, ApiError>,
+{
+    log::debug!("received request! method: {:?}, url: {:?}, headers: {:?}",
+        request.method(),
+        request.url(),
+        request.headers()
+    );
+
+    let context = Context {
+        deadline: Instant::now() + options.request_timeout,
+        max_body: options.max_body,
+    };
+    // A panic fails this request, not the worker
+    let reply = panic::catch_unwind(AssertUnwindSafe(|| handler(&mut request, &context)))
+        .unwrap_or_else(|_| Err(ApiError::internal("Internal error")))
+        .unwrap_or_else(|err| err.into_response().into());
+
+    let response = match reply {
+        Reply::Response(response) => response,
+        Reply::Stream(stream) => {
+            let writer = request.into_writer();
+            thread::spawn(move || stream(writer));
+            return;
+        }
+    };
+
+    // The client may have gone away; that is its problem, not the server's
+    if let Err(err) = request.respond(response) {
+        log::warn!("failed to send response: {}", err);
+    }
+}